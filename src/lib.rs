@@ -1,9 +1,48 @@
 #![allow(dead_code)]
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+
+/// Verbosity level of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity
+{
+    /// Only fatal errors are reported
+    Quiet,
+    /// Errors and warnings are reported
+    Normal,
+    /// Additional information is reported
+    Verbose,
+    /// Everything is reported, including diagnostic details
+    Debug
+}
+
+/// Current verbosity level, stored as the discriminant of a Verbosity
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set the verbosity level used throughout the library
+pub fn set_verbosity(level: Verbosity)
+{
+    let _lock = lock_settings();
+    VERBOSITY.store(level as u8, Ordering::Relaxed);
+}
+
+/// Get the verbosity level used throughout the library
+pub fn verbosity() -> Verbosity
+{
+    match VERBOSITY.load(Ordering::Relaxed)
+    {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Debug
+    }
+}
 
 /// Levels of severity for a CliError
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorLevel
 {
     /// Fatal error: the application cannot continue
@@ -12,6 +51,93 @@ pub enum ErrorLevel
     Warning
 }
 
+impl std::str::FromStr for ErrorLevel
+{
+    type Err = ();
+
+    /// Parse an ErrorLevel from `error` or `warning`, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s.to_lowercase().as_str()
+        {
+            "error" => Ok(ErrorLevel::Error),
+            "warning" => Ok(ErrorLevel::Warning),
+            _ => Err(())
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorLevel
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            ErrorLevel::Error => write!(f, "error"),
+            ErrorLevel::Warning => write!(f, "warning")
+        }
+    }
+}
+
+/// Get the prefix (such as `error: `) for a message of the given level
+/// printed to stderr, colored if decorations are enabled for stderr
+pub fn level_prefix(level: ErrorLevel) -> String
+{
+    let color = match level
+    {
+        ErrorLevel::Error => AnsiColor::Red,
+        ErrorLevel::Warning => AnsiColor::Yellow
+    };
+
+    if should_colorize(Stream::Stderr)
+    {
+        decorate(level.to_string(), color, AnsiStyle::Bold) + ": "
+    }
+    else
+    {
+        format!("{}: ", level)
+    }
+}
+
+/// Print an error to stderr with a colored `error: ` prefix, accepting the
+/// same arguments as `eprintln!`. Like reported errors, it is printed even
+/// when running quietly
+#[macro_export]
+macro_rules! eprintln_error
+{
+    ($($arg:tt)*) =>
+    {
+        eprintln!("{}{}", $crate::level_prefix($crate::ErrorLevel::Error), format_args!($($arg)*))
+    };
+}
+
+/// Print a warning to stderr with a colored `warning: ` prefix, accepting the
+/// same arguments as `eprintln!`. Nothing is printed when running quietly
+#[macro_export]
+macro_rules! eprintln_warn
+{
+    ($($arg:tt)*) =>
+    {
+        if $crate::verbosity() != $crate::Verbosity::Quiet
+        {
+            eprintln!("{}{}", $crate::level_prefix($crate::ErrorLevel::Warning), format_args!($($arg)*))
+        }
+    };
+}
+
+/// Error code used by `CliError::error_default`
+static DEFAULT_ERROR_CODE: AtomicI32 = AtomicI32::new(1);
+
+/// A value in a flat JSON object, as read by `CliError::from_json`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JsonValue
+{
+    /// A string, with its escaping undone
+    String(String),
+    /// A number, boolean or null, as written
+    Literal(String)
+}
+
 /// Error for a CLI application
 #[derive(Debug, Clone)]
 pub struct CliError
@@ -23,7 +149,15 @@ pub struct CliError
     /// Error Level
     pub error_level: ErrorLevel,
     /// Has the error been reported to the user yet?
-    reported: bool
+    reported: bool,
+    /// Error which caused this error
+    source: Option<Box<CliError>>,
+    /// Name of the subsystem which produced the error
+    group: Option<String>,
+    /// Key/value pairs describing the circumstances of the error
+    context: Vec<(String, String)>,
+    /// Link to documentation about the error
+    doc_link: Option<String>
 }
 
 impl CliError
@@ -34,639 +168,5384 @@ impl CliError
         CliError
         {
             error: String::from(msg),
-            error_code: error_code,
-            error_level: error_level,
-            reported: false
+            error_code,
+            error_level,
+            reported: false,
+            source: None,
+            group: None,
+            context: vec![],
+            doc_link: None
         }
     }
 
-    /// Generate a new Err(CliError) with level ErrorLevel::Warning
-    pub fn warn<T>(msg: &str, error_code: i32) -> Result<T, CliError>
+    /// Associate the error with the name of the subsystem which produced it
+    pub fn with_group(mut self, group: &str) -> Self
     {
-        Err(CliError::new(msg, error_code, ErrorLevel::Warning))
+        self.group = Some(String::from(group));
+        self
     }
 
-    /// Generate a new Err(CliError) with level ErrorLevel::Error
-    pub fn error<T>(msg: &str, error_code: i32) -> Result<T, CliError>
+    /// Get the name of the subsystem which produced the error
+    pub fn group(&self) -> Option<&str>
     {
-        Err(CliError::new(msg, error_code, ErrorLevel::Error))
+        self.group.as_deref()
     }
 
-    /// Handles the error appropriately, either by simply alerting the user, or
-    /// halting execution
-    pub fn handle(&mut self) -> Result<(), CliError>
+    /// Lower the level of the error to ErrorLevel::Warning
+    pub fn demote(mut self) -> Self
     {
-        match self.error_level
-        {
-            ErrorLevel::Error => 
-            {
-                // If the error has not been reported, report it
-                if !self.reported
-                {
-                    eprintln!("{} has encountered an error: '{}'", env!("CARGO_PKG_NAME"), self.error);
-                    self.reported = true;
-                }
-                
-                // Pass the error up the call stack
-                Err(self.clone())
-            },
-            ErrorLevel::Warning =>
-            {
-                // If the warning has not been reported, report it
-                if !self.reported
-                {
-                    eprintln!("{} has encountered a warning: '{}'", env!("CARGO_PKG_NAME"), self.error);
-                }
+        self.error_level = ErrorLevel::Warning;
+        self
+    }
 
-                // Do not pass the error up the call stack
-                Ok(())
-            }
-        }
+    /// Raise the level of the error to ErrorLevel::Error
+    pub fn promote(mut self) -> Self
+    {
+        self.error_level = ErrorLevel::Error;
+        self
     }
 
-    /// Dismiss error of a given error code
-    pub fn dismiss_by_code(&self, error_code: i32) -> Result<(), CliError>
+    /// Lower the level of the error to ErrorLevel::Warning if `condition` is
+    /// true
+    pub fn as_warning_if(self, condition: bool) -> Self
     {
-        if self.error_code == error_code
-        {
-            Ok(())
-        }
-        else
-        {
-            Err(self.clone())
-        }
+        if condition { self.demote() } else { self }
     }
 
-    /// Dismiss error if the error code is in a vector of error codes
-    pub fn dismiss_by_codes(&self, error_codes: Vec<i32>) -> Result<(), CliError>
+    /// Raise the level of the error to ErrorLevel::Error if `condition` is true
+    pub fn as_error_if(self, condition: bool) -> Self
     {
-        if error_codes.contains(&self.error_code)
-        {
-            Ok(())
-        }
-        else
+        if condition { self.promote() } else { self }
+    }
+
+    /// Attach an error as the cause of this error, if this error already has a
+    /// cause, the error is attached to the end of the chain
+    pub fn with_source(mut self, source: CliError) -> Self
+    {
+        self.source = Some(Box::new(match self.source
         {
-            Err(self.clone())
-        }
+            Some(existing) => existing.with_source(source),
+            None => source
+        }));
+
+        self
     }
 
-    /// Dismisses the error (A clearer syntax than just ignoring and returning Ok(()))
-    pub fn dismiss(&self) -> Result<(), CliError>
+    /// Get the error which caused this error
+    pub fn source(&self) -> Option<&CliError>
     {
-        Ok(())
+        self.source.as_deref()
     }
-}
 
+    /// Attach a key/value pair describing the circumstances of the error, such
+    /// as the file being processed
+    pub fn with_context(mut self, key: &str, value: &str) -> Self
+    {
+        self.context.push((String::from(key), String::from(value)));
+        self
+    }
 
-/// Command line arguments
-#[derive(Debug)]
-pub struct Arguments
-{
-    /// Defined Arguments
-    pub args: Vec<String>,
-    /// Argument Values
-    pub values: HashMap<String, String>,
-    /// List of raw arguments
-    pub naked_values: Vec<String>
-}
+    /// Get the key/value pairs describing the circumstances of the error
+    pub fn context(&self) -> &[(String, String)]
+    {
+        &self.context
+    }
 
+    /// Attach a link to documentation about the error, displayed below the
+    /// error when it is reported
+    pub fn with_doc_link(mut self, url: &str) -> Self
+    {
+        self.doc_link = Some(String::from(url));
+        self
+    }
 
-impl Arguments
-{
-    /// Generate a new Arguments object from the command line arguments
-    pub fn new(raw_args: std::env::Args) -> Arguments
+    /// Get the link to documentation about the error
+    pub fn doc_link(&self) -> Option<&str>
     {
-        let mut args: Vec<String> = vec![];
-        let mut naked_values: Vec<String> = vec![];
+        self.doc_link.as_deref()
+    }
 
-        let mut values: HashMap<String, String> = HashMap::new();
+    /// On an error, attempt to recover by calling `f` with the error. If `f`
+    /// fails as well, the original error is chained as the cause of the new one
+    pub fn try_recover<T, F: FnOnce(&CliError) -> Result<T, CliError>>(result: Result<T, CliError>, f: F) -> Result<T, CliError>
+    {
+        match result
+        {
+            Ok(v) => Ok(v),
+            Err(e) => f(&e).map_err(|fallback| fallback.with_source(e))
+        }
+    }
 
-        let mut last_arg = String::from("");
+    /// Wrap the error in `Err`, for returning it from a function
+    pub fn into_result<T>(self) -> Result<T, CliError>
+    {
+        Err(self)
+    }
 
-        let arg_str_array: Vec<String> = raw_args.collect();
+    /// Wrap the error in `Err`, the same as `into_result`
+    pub fn into_err<T>(self) -> Result<T, CliError>
+    {
+        self.into_result()
+    }
 
-        for arg in &arg_str_array[1..]
-        {
-            if arg.starts_with("--")
-            {
-                if naked_values.len() != 0
-                {
-                    values.insert(last_arg.clone(), if naked_values.len() > 0 {naked_values[0].clone()} else {String::new()});
-                    naked_values = vec![];
-                }
+    /// Call `f` with the value of a successful result, passing any error
+    /// through unchanged
+    pub fn and_then<T, U, F: FnOnce(T) -> Result<U, CliError>>(result: Result<T, CliError>, f: F) -> Result<U, CliError>
+    {
+        result.and_then(f)
+    }
 
-                args.push(arg.clone());
-                last_arg = arg.clone();
-            }
-            else if arg.starts_with("-")
-            {
-                if naked_values.len() != 0
-                {
-                    values.insert(last_arg.clone(), if naked_values.len() > 0 {naked_values[0].clone()} else {String::new()});
-                    naked_values = vec![];
-                }
+    /// Call `f` until it succeeds, up to `attempts` times, waiting `delay`
+    /// between attempts. Warnings are returned without retrying, and on
+    /// running out of attempts the last error is returned with the number of
+    /// attempts in its context
+    pub fn retry<T, F: FnMut() -> Result<T, CliError>>(attempts: usize, delay: std::time::Duration, mut f: F) -> Result<T, CliError>
+    {
+        let attempts = attempts.max(1);
+        let mut attempt = 1;
 
-                if arg.len() == 2
-                {
-                    args.push(arg.clone());
-                    last_arg = arg.clone();
-                }
-                else
+        loop
+        {
+            match f()
+            {
+                Ok(v) => return Ok(v),
+                Err(e) if e.error_level == ErrorLevel::Warning => return Err(e),
+                Err(e) if attempt >= attempts => return Err(e.with_context("attempts", &attempts.to_string())),
+                Err(_) =>
                 {
-                    for c in arg.chars()
-                    {
-                        if c != '-'
-                        {
-                            let current_arg = String::from("-") + &c.to_string();
-                            args.push(current_arg.clone());
-                            last_arg = current_arg;
-                        }
-                    }
+                    std::thread::sleep(delay);
+                    attempt += 1;
                 }
             }
-            else
-            {
-                naked_values.push(arg.clone());
-            }
         }
+    }
 
-        if naked_values.len() != 0
-        {
-            values.insert(last_arg.clone(), naked_values[0].clone());
-        }
+    /// Convert any error into a CliError with level ErrorLevel::Error, using the
+    /// error's message
+    pub fn convert<E: std::error::Error>(err: E, code: i32) -> CliError
+    {
+        CliError::new(&err.to_string(), code, ErrorLevel::Error)
+    }
 
-        Arguments
+    /// Generate a new CliError object with the level parsed from a string such
+    /// as `error` or `warning`
+    pub fn new_with_level_string(msg: &str, error_code: i32, level: &str) -> Result<CliError, CliError>
+    {
+        match level.parse::<ErrorLevel>()
         {
-            args,
-            values,
-            naked_values
+            Ok(error_level) => Ok(CliError::new(msg, error_code, error_level)),
+            Err(_) => CliError::error(&format!("Unrecognised error level '{}'", level), 1)
         }
     }
 
-    /// Extract a single value passed as the value of an option
-    pub fn get_single(&self, key: &str) -> Option<String>
+    /// Generate a new Err(CliError) with level ErrorLevel::Warning
+    pub fn warn<T>(msg: &str, error_code: i32) -> Result<T, CliError>
     {
-        match self.values.get(&String::from(key))
-        {
-            Some(s) => 
-            {
-                if s.len() > 0
-                {
-                    Some(s.clone())
-                }
-                else
-                {
-                    None
-                }
-            },
-            None => None
-        }
+        Err(CliError::new(msg, error_code, ErrorLevel::Warning))
     }
 
-    /// Checks if an argument was given
-    pub fn check_arg(&self, arg: &str) -> bool
+    /// Generate a new Err(CliError) with level ErrorLevel::Error
+    pub fn error<T>(msg: &str, error_code: i32) -> Result<T, CliError>
     {
-        self.args.contains(&String::from(arg))
+        Err(CliError::new(msg, error_code, ErrorLevel::Error))
     }
 
-    /// Get Passed value
-    pub fn get_passed<T: std::str::FromStr>(&self, arg: &str) -> Option<T>
+    /// Generate a new Err(CliError) with level ErrorLevel::Error and the
+    /// default error code
+    pub fn error_default<T>(msg: &str) -> Result<T, CliError>
     {
-        if !self.check_arg(arg)
-        {
-            return None;
-        }
+        Err(CliError::new(msg, CliError::default_code(), ErrorLevel::Error))
+    }
 
-        match self.get_single(arg)
+    /// Set the error code used by `error_default`
+    pub fn set_default_code(code: i32)
+    {
+        let _lock = lock_settings();
+        DEFAULT_ERROR_CODE.store(code, Ordering::Relaxed);
+    }
+
+    /// Get the error code used by `error_default`
+    pub fn default_code() -> i32
+    {
+        DEFAULT_ERROR_CODE.load(Ordering::Relaxed)
+    }
+
+    /// Get a compact form of the error, `[code] message`, for embedding in
+    /// other text
+    pub fn short(&self) -> String
+    {
+        format!("[{}] {}", self.error_code, self.error)
+    }
+
+    /// Serialize the error into `key=value` lines, with newlines and
+    /// backslashes in the message escaped
+    pub fn to_kv_string(&self) -> String
+    {
+        let message = self.error.replace('\\', "\\\\").replace('\n', "\\n");
+
+        format!("level={}\ncode={}\nmessage={}\nreported={}\n", self.error_level, self.error_code, message, self.reported)
+    }
+
+    /// Deserialize an error from the `key=value` lines produced by
+    /// `to_kv_string`
+    #[allow(clippy::result_unit_err)]
+    pub fn from_kv_string(s: &str) -> Result<CliError, ()>
+    {
+        let mut level = None;
+        let mut code = None;
+        let mut message = None;
+        let mut reported = false;
+
+        for line in s.lines()
         {
-            Some(s) =>
-            {
-                match s.parse::<T>()
-                {
-                    Ok(v) => Some(v),
-                    Err(_) => None
-                }
-            },
-            None =>
+            let (key, value) = line.split_once('=').ok_or(())?;
+
+            match key
             {
-                None
+                "level" => level = Some(value.parse::<ErrorLevel>()?),
+                "code" => code = Some(value.parse::<i32>().map_err(|_| ())?),
+                "message" => message = Some(CliError::unescape_message(value)),
+                "reported" => reported = value.parse::<bool>().map_err(|_| ())?,
+                _ => {}
             }
         }
+
+        let mut error = CliError::new(&message.ok_or(())?, code.ok_or(())?, level.ok_or(())?);
+        error.reported = reported;
+
+        Ok(error)
     }
 
-    /// Get a passed value while checking if the result is None, and if so it returns a CliError
-    pub fn get_passed_checked<T: std::str::FromStr>(&self, arg: &str) -> Result<T, CliError>
+    /// Undo the escaping of newlines and backslashes in a serialized message
+    fn unescape_message(s: &str) -> String
     {
-        if !self.check_arg(arg)
-        {
-            return CliError::error(&format!("No '{}' option passed", arg), 1);
-        }
+        let mut result = String::new();
+        let mut chars = s.chars();
 
-        match self.get_single(arg)
+        while let Some(c) = chars.next()
         {
-            Some(s) =>
+            if c == '\\'
             {
-                match s.parse::<T>()
+                match chars.next()
                 {
-                    Ok(v) => Ok(v),
-                    Err(_) => CliError::error(&format!("Cannot parse argument to '{}'", arg), 1)
+                    Some('n') => result.push('\n'),
+                    Some(other) => result.push(other),
+                    None => result.push('\\')
                 }
-            },
-            None =>
+            }
+            else
             {
-                CliError::error(&format!("No argument passed to '{}'", arg), 1)
+                result.push(c);
             }
         }
-    }
-}
 
+        result
+    }
 
-/// Colors which can be used in the console
-pub enum AnsiColor
-{
-    Black,
-    Red,
-    Green,
-    Yellow,
-    Blue,
-    Purple,
-    Cyan,
-    White,
-    BrightBlack,
-    BrightRed,
-    BrightGreen,
-    BrightYellow,
-    BrightBlue,
-    BrightPurple,
-    BrightCyan,
-    BrightWhite
-}
+    /// Build the message used to report the error to the user
+    fn report_message(&self) -> String
+    {
+        let kind = match self.error_level
+        {
+            ErrorLevel::Error => "an error",
+            ErrorLevel::Warning => "a warning"
+        };
 
+        let name = match &self.group
+        {
+            Some(group) => format!("{} [{}]", env!("CARGO_PKG_NAME"), group),
+            None => String::from(env!("CARGO_PKG_NAME"))
+        };
 
-/// Styles which can use used in the console
-pub enum AnsiStyle
-{
-    Bold,
-    Underline,
-    Strikethrough
-}
+        let mut message = format!("{} has encountered {}: '{}'", name, kind, self.error);
 
-/// Clear all of the decorations from a string
-pub fn clear_decoration(s: String) -> String
-{
-    let mut result = String::from("");
+        if verbosity() == Verbosity::Debug
+        {
+            message += &format!(" (code {})", self.error_code);
+        }
+
+        message
+    }
+
+    /// Serialize the error into a JSON object with the fields `level`,
+    /// `code`, `message` and `reported`
+    pub fn to_json(&self) -> String
+    {
+        format!("{{\"level\":\"{}\",\"code\":{},\"message\":\"{}\",\"reported\":{}}}", self.error_level, self.error_code, CliError::escape_json(&self.error), self.reported)
+    }
+
+    /// Deserialize an error from a JSON object as produced by `to_json`.
+    /// Only flat objects of strings, numbers and booleans are supported
+    pub fn from_json(s: &str) -> Result<CliError, CliError>
+    {
+        let fields = match CliError::parse_json_object(s)
+        {
+            Some(fields) => fields,
+            None => return CliError::error("Cannot parse error from invalid JSON", 1)
+        };
+
+        let field = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+
+        let level = match field("level")
+        {
+            Some(JsonValue::String(level)) => match level.parse::<ErrorLevel>()
+            {
+                Ok(level) => level,
+                Err(_) => return CliError::error("Invalid error level in JSON", 1)
+            },
+            Some(JsonValue::Literal(_)) => return CliError::error("Invalid error level in JSON", 1),
+            None => return CliError::error("Missing field 'level' in JSON", 1)
+        };
+
+        // The code must be a number, not a string containing one
+        let code = match field("code")
+        {
+            Some(JsonValue::Literal(code)) => match code.parse::<i32>()
+            {
+                Ok(code) => code,
+                Err(_) => return CliError::error("Invalid error code in JSON", 1)
+            },
+            Some(JsonValue::String(_)) => return CliError::error("Invalid error code in JSON", 1),
+            None => return CliError::error("Missing field 'code' in JSON", 1)
+        };
+
+        let message = match field("message")
+        {
+            Some(JsonValue::String(message)) => message,
+            Some(JsonValue::Literal(_)) => return CliError::error("Invalid error message in JSON", 1),
+            None => return CliError::error("Missing field 'message' in JSON", 1)
+        };
+
+        let mut error = CliError::new(message, code, level);
+        error.reported = matches!(field("reported"), Some(JsonValue::Literal(reported)) if reported == "true");
+
+        Ok(error)
+    }
+
+    /// Escape a string for use within a JSON string
+    fn escape_json(s: &str) -> String
+    {
+        let mut result = String::new();
+
+        for c in s.chars()
+        {
+            match c
+            {
+                '"' => result += "\\\"",
+                '\\' => result += "\\\\",
+                '\n' => result += "\\n",
+                '\r' => result += "\\r",
+                '\t' => result += "\\t",
+                c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+                c => result.push(c)
+            }
+        }
+
+        result
+    }
+
+    /// Parse a flat JSON object into its keys and values, with strings
+    /// unescaped and other values kept as written
+    fn parse_json_object(s: &str) -> Option<Vec<(String, JsonValue)>>
+    {
+        let mut chars = s.trim().chars().peekable();
+        let mut fields = vec![];
+
+        let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>|
+        {
+            while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false)
+            {
+                chars.next();
+            }
+        };
+
+        if chars.next()? != '{'
+        {
+            return None;
+        }
+
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&'}')
+        {
+            chars.next();
+            return if chars.next().is_none() { Some(fields) } else { None };
+        }
+
+        loop
+        {
+            skip_whitespace(&mut chars);
+            let key = CliError::parse_json_string(&mut chars)?;
+
+            skip_whitespace(&mut chars);
+            if chars.next()? != ':'
+            {
+                return None;
+            }
+
+            skip_whitespace(&mut chars);
+            let value = if chars.peek() == Some(&'"')
+            {
+                JsonValue::String(CliError::parse_json_string(&mut chars)?)
+            }
+            else
+            {
+                let mut value = String::new();
+                while let Some(c) = chars.peek().filter(|c| **c != ',' && **c != '}')
+                {
+                    value.push(*c);
+                    chars.next();
+                }
+                JsonValue::Literal(String::from(value.trim()))
+            };
+
+            fields.push((key, value));
+
+            skip_whitespace(&mut chars);
+            match chars.next()?
+            {
+                ',' => continue,
+                '}' => break,
+                _ => return None
+            }
+        }
+
+        if chars.next().is_none() { Some(fields) } else { None }
+    }
+
+    /// Parse a JSON string, including its quotes, undoing its escaping
+    fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String>
+    {
+        if chars.next()? != '"'
+        {
+            return None;
+        }
+
+        let mut result = String::new();
+
+        loop
+        {
+            match chars.next()?
+            {
+                '"' => return Some(result),
+                '\\' =>
+                {
+                    match chars.next()?
+                    {
+                        'n' => result.push('\n'),
+                        'r' => result.push('\r'),
+                        't' => result.push('\t'),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'u' =>
+                        {
+                            let mut code = CliError::parse_json_hex(chars)?;
+
+                            // Characters outside the basic multilingual plane
+                            // are escaped as a pair of surrogates
+                            if (0xD800..0xDC00).contains(&code)
+                            {
+                                if chars.next()? != '\\' || chars.next()? != 'u'
+                                {
+                                    return None;
+                                }
+
+                                let low = CliError::parse_json_hex(chars)?;
+
+                                if !(0xDC00..0xE000).contains(&low)
+                                {
+                                    return None;
+                                }
+
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+
+                            result.push(char::from_u32(code)?);
+                        },
+                        other => result.push(other)
+                    }
+                },
+                c => result.push(c)
+            }
+        }
+    }
+
+    /// Parse the four hex digits of a `\u` escape in a JSON string
+    fn parse_json_hex(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<u32>
+    {
+        let hex: String = chars.by_ref().take(4).collect();
+
+        if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return None;
+        }
+
+        u32::from_str_radix(&hex, 16).ok()
+    }
+
+    /// Build a detailed multi-line report of the error, including the chain of
+    /// errors which caused it and its context, colored if decorations are
+    /// enabled for stderr
+    pub fn report(&self) -> String
+    {
+        let colorize = should_colorize(Stream::Stderr);
+        let heading = |s: &str| if colorize { decorate(String::from(s), AnsiColor::Yellow, AnsiStyle::Bold) } else { String::from(s) };
+
+        let mut report = format!("{}{}\n", level_prefix(self.error_level), self.error);
+
+        if self.source.is_some()
+        {
+            report += &format!("\n{}\n", heading("Caused by:"));
+
+            let mut source = self.source();
+            let mut index = 0;
+            while let Some(cause) = source
+            {
+                report += &format!("  {}: {}\n", index, cause.error);
+                source = cause.source();
+                index += 1;
+            }
+        }
+
+        if !self.context.is_empty()
+        {
+            report += &format!("\n{}\n", heading("Context:"));
+
+            for (key, value) in &self.context
+            {
+                report += &format!("  {}: {}\n", key, value);
+            }
+        }
+
+        if let Some(url) = &self.doc_link
+        {
+            report += &format!("\nSee: {}\n", if colorize { decorate_hyperlink(url.clone(), url) } else { url.clone() });
+        }
+
+        report
+    }
+
+    /// Report the error to the user if it has not been reported yet
+    fn report_once(&mut self)
+    {
+        if self.reported
+        {
+            return;
+        }
+
+        // Warnings are not reported when running quietly
+        if self.error_level == ErrorLevel::Warning && verbosity() == Verbosity::Quiet
+        {
+            return;
+        }
+
+        eprintln!("{}", self.report_message());
+        log_line(&self.report_message());
+
+        if let Some(url) = &self.doc_link
+        {
+            // The link is clickable in terminals which support hyperlinks
+            let link = if should_colorize(Stream::Stderr) { decorate_hyperlink(url.clone(), url) } else { url.clone() };
+
+            eprintln!("  See: {}", link);
+            log_line(&format!("  See: {}", url));
+        }
+
+        self.reported = true;
+    }
+
+    /// Handles the error appropriately, either by simply alerting the user, or
+    /// halting execution
+    pub fn handle(&mut self) -> Result<(), CliError>
+    {
+        match self.error_level
+        {
+            ErrorLevel::Error => 
+            {
+                // If the error has not been reported, report it
+                self.report_once();
+                
+                // Pass the error up the call stack
+                Err(self.clone())
+            },
+            ErrorLevel::Warning =>
+            {
+                // If the warning has not been reported, report it
+                self.report_once();
 
-    let mut within = false;
+                // Do not pass the error up the call stack
+                Ok(())
+            }
+        }
+    }
 
-    for c in s.chars()
+    /// Handle each of the errors, removing them from `errors`. Warnings are
+    /// reported and consumed, while errors are reported and collected into
+    /// the returned list
+    pub fn handle_all(errors: &mut Vec<CliError>) -> Result<(), CliErrorList>
     {
-        if c == '\x1B'
+        let mut fatal = CliErrorList::new();
+
+        for mut error in errors.drain(..)
+        {
+            if let Err(e) = error.handle()
+            {
+                fatal.push(e);
+            }
+        }
+
+        if fatal.is_empty()
+        {
+            Ok(())
+        }
+        else
         {
-            within = true;
+            Err(fatal)
         }
+    }
 
-        if within
+    /// Run `f` and handle any error it returns. If the error is a warning
+    /// execution continues and None is returned, otherwise the process exits
+    /// with the error code
+    pub fn scope<T, F: FnOnce() -> Result<T, CliError>>(f: F) -> Option<T>
+    {
+        match f()
         {
-            if c == 'm'
+            Ok(v) => Some(v),
+            Err(mut e) =>
             {
-                within = false;
+                if e.handle().is_err()
+                {
+                    std::process::exit(e.error_code);
+                }
+
+                None
             }
         }
+    }
+
+    /// Print a message to stdout and exit successfully, for outcomes which
+    /// should be reported but are not errors, such as no files matching
+    pub fn soft_exit(message: &str) -> !
+    {
+        println!("{}", message);
+        std::process::exit(0);
+    }
+
+    /// Report the error without passing it up the call stack, returning the
+    /// given default value so execution can continue
+    pub fn report_and<T>(&mut self, default: T) -> T
+    {
+        self.report_once();
+
+        default
+    }
+
+    /// Dismiss error of a given error code
+    pub fn dismiss_by_code(&self, error_code: i32) -> Result<(), CliError>
+    {
+        if self.error_code == error_code
+        {
+            Ok(())
+        }
         else
         {
-            result += &c.to_string();
+            Err(self.clone())
         }
     }
 
-    result
+    /// Dismiss error if the error code is in a vector of error codes
+    pub fn dismiss_by_codes(&self, error_codes: Vec<i32>) -> Result<(), CliError>
+    {
+        if error_codes.contains(&self.error_code)
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(self.clone())
+        }
+    }
+
+    /// Dismisses the error (A clearer syntax than just ignoring and returning Ok(()))
+    pub fn dismiss(&self) -> Result<(), CliError>
+    {
+        Ok(())
+    }
 }
 
-/// Decorate a string with a color
-pub fn decorate_color(s: String, color: AnsiColor) -> String
+/// Extension methods for results with a CliError
+pub trait CliErrorResultExt
 {
-    let start = String::from(match color
+    /// Call `f` with the error, if there is one, before returning the result,
+    /// such as to add context or report it
+    fn and_handle<F: FnOnce(&mut CliError)>(self, f: F) -> Self;
+}
+
+impl<T> CliErrorResultExt for Result<T, CliError>
+{
+    fn and_handle<F: FnOnce(&mut CliError)>(mut self, f: F) -> Self
     {
-        AnsiColor::Black => "\x1B[30m",
-        AnsiColor::Red => "\x1B[31m",
-        AnsiColor::Green => "\x1B[32m",
-        AnsiColor::Yellow => "\x1B[33m",
-        AnsiColor::Blue => "\x1B[34m",
-        AnsiColor::Purple => "\x1B[35m",
-        AnsiColor::Cyan => "\x1B[36m",
-        AnsiColor::White => "\x1B[37m",
-        AnsiColor::BrightBlack => "\x1B[90m",
-        AnsiColor::BrightRed => "\x1B[91m",
-        AnsiColor::BrightGreen => "\x1B[92m",
-        AnsiColor::BrightYellow => "\x1B[93m",
-        AnsiColor::BrightBlue => "\x1B[94m",
-        AnsiColor::BrightPurple => "\x1B[95m",
-        AnsiColor::BrightCyan => "\x1B[96m",
-        AnsiColor::BrightWhite => "\x1B[97m"
-    });
+        if let Err(e) = &mut self
+        {
+            f(e);
+        }
 
-    start + &clear_decoration(s) + &String::from("\x1B[0m")
+        self
+    }
+}
+
+/// A collection of CliErrors
+#[derive(Debug, Clone, Default)]
+pub struct CliErrorList
+{
+    /// Errors in the list
+    pub errors: Vec<CliError>
+}
+
+impl CliErrorList
+{
+    /// Generate an empty CliErrorList object
+    pub fn new() -> Self
+    {
+        Self
+        {
+            errors: vec![]
+        }
+    }
+
+    /// Add an error to the list
+    pub fn push(&mut self, error: CliError)
+    {
+        self.errors.push(error);
+    }
+
+    /// Get the number of errors in the list
+    pub fn len(&self) -> usize
+    {
+        self.errors.len()
+    }
+
+    /// Check if the list contains no errors
+    pub fn is_empty(&self) -> bool
+    {
+        self.errors.is_empty()
+    }
+
+    /// Get the errors produced by a given subsystem
+    pub fn filter_by_group(&self, group: &str) -> CliErrorList
+    {
+        CliErrorList
+        {
+            errors: self.errors.iter().filter(|error| error.group() == Some(group)).cloned().collect()
+        }
+    }
+}
+
+/// A single occurrence of an option on the command line
+#[derive(Debug, Clone)]
+struct Occurrence
+{
+    /// Name of the option, including its dashes
+    name: String,
+    /// Naked values which followed the option
+    values: Vec<String>,
+    /// Was the value attached with `=`, such as `--level=3`
+    explicit: bool,
+    /// Index of the option in the command line arguments, where the program
+    /// name is at index 0
+    position: usize
+}
+
+impl Occurrence
+{
+    /// Generate a new Occurrence object for an option with no values yet
+    fn new(name: &str, position: usize) -> Self
+    {
+        Self
+        {
+            name: String::from(name),
+            values: vec![],
+            explicit: false,
+            position
+        }
+    }
+
+    /// Generate a new Occurrence object for an option with a value attached
+    /// with `=`
+    fn new_explicit(name: &str, value: &str, position: usize) -> Self
+    {
+        Self
+        {
+            name: String::from(name),
+            values: vec![String::from(value)],
+            explicit: true,
+            position
+        }
+    }
+}
+
+/// A function checking the value of an option
+struct Validator
+{
+    /// Name of the option, including its dashes
+    arg: String,
+    /// Returns true if a value is valid
+    check: Box<dyn Fn(&str) -> bool>
+}
+
+impl std::fmt::Debug for Validator
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        f.debug_struct("Validator").field("arg", &self.arg).finish()
+    }
+}
+
+/// Command line arguments
+#[derive(Debug)]
+pub struct Arguments
+{
+    /// Defined Arguments
+    pub args: Vec<String>,
+    /// Argument Values
+    pub values: HashMap<String, String>,
+    /// List of raw arguments
+    pub naked_values: Vec<String>,
+    /// Arguments given after a `--` separator
+    after_separator: Vec<String>,
+    /// Every occurrence of an option, in the order given
+    occurrences: Vec<Occurrence>,
+    /// Validators for the values of specific options
+    validators: Vec<Validator>
 }
 
-/// Decorate a string with a style
-pub fn decorate_style(s: String, style: AnsiStyle) -> String
-{
-    let start = String::from(match style
+
+impl Arguments
+{
+    /// Generate a new Arguments object from the command line arguments
+    pub fn new(raw_args: std::env::Args) -> Arguments
+    {
+        Arguments::from_vec(raw_args.collect())
+    }
+
+    /// Generate a new Arguments object from a vector of arguments, where the
+    /// first element is the program name
+    pub fn from_vec(arg_str_array: Vec<String>) -> Arguments
+    {
+        Arguments::from_vec_with_prefixes(arg_str_array, &["--"], &["-"])
+    }
+
+    /// Generate a new Arguments object from a vector of arguments, where the
+    /// first element is the program name, recognizing options by the given
+    /// prefixes rather than `--` and `-`. Long options (such as `/verbose` with
+    /// the prefix `/`) are kept whole, while short options (such as `+xy` with
+    /// the prefix `+`) are split into one option per character
+    pub fn from_vec_with_prefixes(arg_str_array: Vec<String>, long_prefixes: &[&str], short_prefixes: &[&str]) -> Arguments
+    {
+        let mut args: Vec<String> = vec![];
+        let mut naked_values: Vec<String> = vec![];
+        let mut after_separator: Vec<String> = vec![];
+        let mut occurrences: Vec<Occurrence> = vec![];
+
+        let mut values: HashMap<String, String> = HashMap::new();
+
+        let mut last_arg = String::from("");
+
+        for (i, arg) in arg_str_array.iter().enumerate().skip(1)
+        {
+            // A prefix on its own (such as a lone dash) is not an option
+            let long_prefix = long_prefixes.iter().find(|prefix| arg.starts_with(**prefix) && arg.len() > prefix.len());
+            let short_prefix = short_prefixes.iter().find(|prefix| arg.starts_with(**prefix) && arg.len() > prefix.len());
+
+            if arg == "--"
+            {
+                // Everything after the separator is a naked value, even if it
+                // starts with a dash
+                after_separator = arg_str_array[i + 1..].to_vec();
+                break;
+            }
+            else if let Some(prefix) = long_prefix
+            {
+                if !naked_values.is_empty()
+                {
+                    values.insert(last_arg.clone(), naked_values[0].clone());
+                    naked_values = vec![];
+                }
+
+                match arg[prefix.len()..].split_once('=')
+                {
+                    Some((name, value)) =>
+                    {
+                        // A value attached with `=` is the only value of the
+                        // option, so following naked values are not taken
+                        let name = String::from(*prefix) + name;
+                        args.push(name.clone());
+                        values.insert(name.clone(), String::from(value));
+                        occurrences.push(Occurrence::new_explicit(&name, value, i));
+                        last_arg = String::new();
+                    },
+                    None =>
+                    {
+                        args.push(arg.clone());
+                        occurrences.push(Occurrence::new(arg, i));
+                        last_arg = arg.clone();
+                    }
+                }
+            }
+            else if let Some(prefix) = short_prefix
+            {
+                if !naked_values.is_empty()
+                {
+                    values.insert(last_arg.clone(), naked_values[0].clone());
+                    naked_values = vec![];
+                }
+
+                if arg[prefix.len()..].chars().count() == 1
+                {
+                    args.push(arg.clone());
+                    occurrences.push(Occurrence::new(arg, i));
+                    last_arg = arg.clone();
+                }
+                else
+                {
+                    for c in arg[prefix.len()..].chars()
+                    {
+                        if !prefix.contains(c)
+                        {
+                            let current_arg = String::from(*prefix) + &c.to_string();
+                            args.push(current_arg.clone());
+                            occurrences.push(Occurrence::new(&current_arg, i));
+                            last_arg = current_arg;
+                        }
+                    }
+                }
+            }
+            else
+            {
+                // A lone dash is a naked value, by convention referring to stdin
+                if let Some(occurrence) = occurrences.last_mut().filter(|occurrence| !occurrence.explicit)
+                {
+                    occurrence.values.push(arg.clone());
+                }
+
+                naked_values.push(arg.clone());
+            }
+        }
+
+        if !naked_values.is_empty()
+        {
+            values.insert(last_arg.clone(), naked_values[0].clone());
+        }
+
+        naked_values.extend(after_separator.iter().cloned());
+
+        Arguments
+        {
+            args,
+            values,
+            naked_values,
+            after_separator,
+            occurrences,
+            validators: vec![]
+        }
+    }
+
+    /// Generate a new Arguments object directly from a set of arguments, the
+    /// values given to each argument, and the positional arguments. Each value
+    /// is treated as a separate occurrence of its argument, and the last value
+    /// is used for single value lookups. Positions are counted as if the
+    /// program name came before the arguments
+    pub fn from_values(args: Vec<String>, values: HashMap<String, Vec<String>>, positionals: Vec<String>) -> Arguments
+    {
+        let mut occurrences: Vec<Occurrence> = vec![];
+
+        for (i, arg) in args.iter().enumerate()
+        {
+            match values.get(arg)
+            {
+                Some(arg_values) if !arg_values.is_empty() =>
+                {
+                    // The values of a repeated argument are only added once
+                    if args[..i].contains(arg)
+                    {
+                        continue;
+                    }
+
+                    for value in arg_values
+                    {
+                        let mut occurrence = Occurrence::new(arg, i + 1);
+                        occurrence.values.push(value.clone());
+                        occurrences.push(occurrence);
+                    }
+                },
+                _ => occurrences.push(Occurrence::new(arg, i + 1))
+            }
+        }
+
+        Arguments
+        {
+            args,
+            values: values.into_iter()
+                .filter_map(|(arg, arg_values)| arg_values.last().cloned().map(|value| (arg, value)))
+                .collect(),
+            naked_values: positionals,
+            after_separator: vec![],
+            occurrences,
+            validators: vec![]
+        }
+    }
+
+    /// Register a function which checks the values given to an option, to be
+    /// run by `validate_all`
+    pub fn add_validator<F: Fn(&str) -> bool + 'static>(&mut self, arg: &str, f: F)
+    {
+        self.validators.push(Validator
+        {
+            arg: String::from(arg),
+            check: Box::new(f)
+        });
+    }
+
+    /// Run the registered validators against the values given to their options,
+    /// returning an error for each invalid value
+    pub fn validate_all(&self) -> Result<(), CliErrorList>
+    {
+        let mut errors = CliErrorList::new();
+
+        for validator in &self.validators
+        {
+            for value in self.occurrence_values(&validator.arg)
+            {
+                if !(validator.check)(value)
+                {
+                    errors.push(CliError::new(&format!("Invalid value '{}' for '{}'", value, validator.arg), 1, ErrorLevel::Error));
+                }
+            }
+        }
+
+        if errors.is_empty()
+        {
+            Ok(())
+        }
+        else
+        {
+            Err(errors)
+        }
+    }
+
+    /// Check if no options and no positional arguments were given, such as when
+    /// a program is run with nothing but its name
+    pub fn is_empty(&self) -> bool
+    {
+        self.args.is_empty() && self.naked_values.is_empty()
+    }
+
+    /// Get the arguments given after a `--` separator
+    pub fn args_after_separator(&self) -> &[String]
+    {
+        &self.after_separator
+    }
+
+    /// Get the value given to each occurrence of an option, in the order given.
+    /// Occurrences without a value are skipped
+    fn occurrence_values(&self, arg: &str) -> Vec<&String>
+    {
+        self.occurrences.iter()
+            .filter(|occurrence| occurrence.name == arg)
+            .filter_map(|occurrence| occurrence.values.first())
+            .collect()
+    }
+
+    /// Parse the value given to each occurrence of an option, such as
+    /// `--id 1 --id 2`, failing on the first value which cannot be parsed
+    pub fn get_passed_all<T: std::str::FromStr>(&self, arg: &str) -> Result<Vec<T>, CliError>
+    {
+        self.occurrence_values(arg).into_iter().enumerate().map(|(i, value)|
+        {
+            value.parse::<T>().or_else(|_| CliError::error(&format!("Cannot parse value '{}' at index {} of '{}'", value, i, arg), 1))
+        }).collect()
+    }
+
+    /// Get the value given to each occurrence of an option, such as
+    /// `--tag a --tag b`, skipping values which cannot be parsed
+    pub fn get_multi_passed<T: std::str::FromStr>(&self, arg: &str) -> Vec<T>
+    {
+        self.occurrence_values(arg).into_iter().filter_map(|value| value.parse::<T>().ok()).collect()
+    }
+
+    /// Get the values given to an option as a flat list, whether the option is
+    /// repeated (`--tag a --tag b`), given a list separated by `sep`
+    /// (`--tag a,b`), or both. Empty items are skipped
+    pub fn get_multi(&self, key: &str, sep: char) -> Vec<String>
+    {
+        self.occurrence_values(key).into_iter()
+            .flat_map(|value| value.split(sep))
+            .map(|item| String::from(item.trim()))
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    /// Collect the `key=value` values given to each occurrence of an option,
+    /// such as `--set a=1 --set b=2`, into a map. Values without an `=` are
+    /// mapped to an empty string
+    pub fn get_map(&self, flag: &str) -> HashMap<String, String>
+    {
+        self.occurrence_values(flag).into_iter().map(|value|
+        {
+            match value.split_once('=')
+            {
+                Some((key, value)) => (String::from(key), String::from(value)),
+                None => (value.clone(), String::new())
+            }
+        }).collect()
+    }
+
+    /// Get the first `arity` values of every occurrence of an option, in the
+    /// order given. Occurrences with fewer than `arity` values are skipped.
+    /// The values must be separate arguments: `--opt=a b` gives the option
+    /// only the value `a`
+    pub fn get_tuples(&self, arg: &str, arity: usize) -> Vec<Vec<String>>
+    {
+        self.occurrences.iter()
+            .filter(|occurrence| occurrence.name == arg && occurrence.values.len() >= arity)
+            .map(|occurrence| occurrence.values[..arity].to_vec())
+            .collect()
+    }
+
+    /// Get the pair of values given to each occurrence of an option, such as
+    /// `--rename old new --rename a b`, in the order given. Both values must
+    /// be separate arguments, so `--rename=old new` gives no pair
+    pub fn get_pairs(&self, arg: &str) -> Vec<(String, String)>
+    {
+        self.get_tuples(arg, 2).into_iter()
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+
+    /// Map each of the given names to the positional argument at the same
+    /// index, failing if there are fewer positional arguments than names
+    pub fn bind_positionals(&self, names: &[&str]) -> Result<HashMap<String, String>, CliError>
+    {
+        let mut bound = HashMap::new();
+
+        for (i, name) in names.iter().enumerate()
+        {
+            match self.naked_values.get(i)
+            {
+                Some(value) => { bound.insert(String::from(*name), value.clone()); },
+                None => return CliError::error(&format!("Missing positional argument '{}'", name), 1)
+            }
+        }
+
+        Ok(bound)
+    }
+
+    /// Print `Usage: {usage}` to stderr and exit with code 1 if no positional
+    /// arguments were given, otherwise return the arguments for chaining
+    pub fn print_usage_if_no_positionals(&self, usage: &str) -> &Arguments
+    {
+        if self.naked_values.is_empty()
+        {
+            eprintln!("Usage: {}", usage);
+            std::process::exit(1);
+        }
+
+        self
+    }
+
+    /// Get the positional arguments, failing if there are fewer than `min` or
+    /// more than `max` of them
+    pub fn positionals_checked(&self, min: usize, max: Option<usize>) -> Result<Vec<String>, CliError>
+    {
+        let count = self.naked_values.len();
+
+        if count < min
+        {
+            return CliError::error(&format!("Expected at least {} positional argument{}, got {}", min, if min == 1 { "" } else { "s" }, count), 1);
+        }
+
+        if let Some(max) = max
+        {
+            if count > max
+            {
+                return CliError::error(&format!("Expected at most {} positional argument{}, got {}", max, if max == 1 { "" } else { "s" }, count), 1);
+            }
+        }
+
+        Ok(self.naked_values.clone())
+    }
+
+    /// Derive the verbosity level from the number of `-v`/`--verbose` and
+    /// `-q`/`--quiet` flags given
+    pub fn verbosity(&self) -> Verbosity
+    {
+        let count = |short: &str, long: &str| self.args.iter().filter(|arg| *arg == short || *arg == long).count();
+
+        if count("-q", "--quiet") > 0
+        {
+            return Verbosity::Quiet;
+        }
+
+        match count("-v", "--verbose")
+        {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::Debug
+        }
+    }
+
+    /// Extract a single value passed as the value of an option. An empty value
+    /// given explicitly with `=` (such as `--key=`) is returned as an empty
+    /// string, while an option given without a value (such as `--key`) has no
+    /// value
+    pub fn get_single(&self, key: &str) -> Option<String>
+    {
+        match self.values.get(&String::from(key))
+        {
+            Some(s) => 
+            {
+                if !s.is_empty() || self.is_explicitly_empty(key)
+                {
+                    Some(s.clone())
+                }
+                else
+                {
+                    None
+                }
+            },
+            None => None
+        }
+    }
+
+    /// Check if the last occurrence of an option was given an empty value with
+    /// `=`, such as `--key=`
+    fn is_explicitly_empty(&self, key: &str) -> bool
+    {
+        self.occurrences.iter()
+            .rev()
+            .find(|occurrence| occurrence.name == key)
+            .map(|occurrence| occurrence.explicit && occurrence.values.first().map(|value| value.is_empty()).unwrap_or(false))
+            .unwrap_or(false)
+    }
+
+    /// Extract a single value passed as the value of an option with leading
+    /// and trailing whitespace removed, or None if nothing else remains
+    pub fn get_single_trimmed(&self, key: &str) -> Option<String>
+    {
+        self.get_single(key)
+            .map(|value| String::from(value.trim()))
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Extract a single value passed as the value of an option converted to
+    /// lowercase, leaving the stored value unchanged
+    pub fn get_single_lower(&self, key: &str) -> Option<String>
+    {
+        self.get_single(key).map(|value| value.to_lowercase())
+    }
+
+    /// Extract a single value passed as the value of an option converted to
+    /// uppercase, leaving the stored value unchanged
+    pub fn get_single_upper(&self, key: &str) -> Option<String>
+    {
+        self.get_single(key).map(|value| value.to_uppercase())
+    }
+
+    /// Extract a single value passed as the value of an option, falling back to
+    /// the value of an environment variable
+    pub fn get_single_or_env(&self, key: &str, env_var: &str) -> Option<String>
+    {
+        match self.get_single(key)
+        {
+            Some(s) => Some(s),
+            None => std::env::var(env_var).ok()
+        }
+    }
+
+    /// Checks if an argument was given
+    pub fn check_arg(&self, arg: &str) -> bool
+    {
+        self.args.contains(&String::from(arg))
+    }
+
+    /// Check that exactly one of a group of arguments was given
+    pub fn require_exactly_one(&self, group: &[&str]) -> Result<(), CliError>
+    {
+        let quote = |args: &[&str]| args.iter().map(|arg| format!("'{}'", arg)).collect::<Vec<String>>().join(", ");
+
+        let present: Vec<&str> = group.iter().copied().filter(|arg| self.check_arg(arg)).collect();
+
+        match present.len()
+        {
+            1 => Ok(()),
+            0 => CliError::error(&format!("One of {} must be passed", quote(group)), 1),
+            _ => CliError::error(&format!("Only one of {} may be passed, found {}", quote(group), quote(&present)), 1)
+        }
+    }
+
+    /// Get Passed value
+    pub fn get_passed<T: std::str::FromStr>(&self, arg: &str) -> Option<T>
+    {
+        if !self.check_arg(arg)
+        {
+            return None;
+        }
+
+        match self.get_single(arg)
+        {
+            Some(s) => s.parse::<T>().ok(),
+            None => None
+        }
+    }
+
+    /// Get a passed string value, failing as `get_passed_checked` does if it is
+    /// missing, or if it is blank, such as with `--name=` or `--name " "`
+    pub fn get_passed_non_empty(&self, arg: &str) -> Result<String, CliError>
+    {
+        let value = self.get_passed_checked::<String>(arg)?;
+
+        if value.trim().is_empty()
+        {
+            return CliError::error(&format!("argument {} must not be empty", arg), 1);
+        }
+
+        Ok(value)
+    }
+
+    /// Get the indices in the command line arguments at which an option was
+    /// given, where the program name is at index 0. Combined short options
+    /// (such as `-abc`) share the index of the argument they were given in
+    pub fn arg_positions(&self, arg: &str) -> Vec<usize>
+    {
+        self.occurrences.iter()
+            .filter(|occurrence| occurrence.name == arg)
+            .map(|occurrence| occurrence.position)
+            .collect()
+    }
+
+    /// Get the value of a flag which optionally takes a value, such as
+    /// `--verbose` or `--verbose=3`. Returns `bare_value` if the flag is given
+    /// without a value attached with `=`, and None if the flag is not given or
+    /// its value cannot be parsed
+    pub fn get_flag_value_or_bare<T: std::str::FromStr>(&self, arg: &str, bare_value: T) -> Option<T>
+    {
+        let occurrence = self.occurrences.iter().rev().find(|occurrence| occurrence.name == arg)?;
+
+        if occurrence.explicit
+        {
+            occurrence.values.first()?.parse::<T>().ok()
+        }
+        else
+        {
+            Some(bare_value)
+        }
+    }
+
+    /// Get a passed value, distinguishing an absent option (`Ok(None)`) from a
+    /// value which fails to parse (`Err`)
+    pub fn try_get_passed<T: std::str::FromStr>(&self, arg: &str) -> Result<Option<T>, T::Err>
+    {
+        if !self.check_arg(arg)
+        {
+            return Ok(None);
+        }
+
+        match self.get_single(arg)
+        {
+            Some(s) => s.parse::<T>().map(Some),
+            None => Ok(None)
+        }
+    }
+
+    /// Get a passed value while checking if the result is None, and if so it returns a CliError
+    pub fn get_passed_checked<T: std::str::FromStr>(&self, arg: &str) -> Result<T, CliError>
+    {
+        self.get_passed_checked_code(arg, 1)
+    }
+
+    /// Get a passed value while checking if the result is None, and if so it
+    /// returns a CliError with the given error code
+    pub fn get_passed_checked_code<T: std::str::FromStr>(&self, arg: &str, code: i32) -> Result<T, CliError>
+    {
+        if !self.check_arg(arg)
+        {
+            return CliError::error(&format!("No '{}' option passed", arg), code);
+        }
+
+        match self.get_single(arg)
+        {
+            Some(s) =>
+            {
+                match s.parse::<T>()
+                {
+                    Ok(v) => Ok(v),
+                    Err(_) => CliError::error(&format!("Cannot parse argument to '{}'", arg), code)
+                }
+            },
+            None =>
+            {
+                CliError::error(&format!("No argument passed to '{}'", arg), code)
+            }
+        }
+    }
+
+    /// Get a passed value which must match one of the given choices, returning
+    /// the matching choice
+    fn get_choice_matching(&self, arg: &str, choices: &[&str], case_insensitive: bool) -> Result<String, CliError>
+    {
+        let value = self.get_passed_checked::<String>(arg)?;
+
+        let found = choices.iter().find(|choice|
+        {
+            if case_insensitive
+            {
+                choice.to_lowercase() == value.to_lowercase()
+            }
+            else
+            {
+                **choice == value
+            }
+        });
+
+        match found
+        {
+            Some(choice) => Ok(String::from(*choice)),
+            None =>
+            {
+                let expected = choices.iter().map(|choice| format!("'{}'", choice)).collect::<Vec<String>>().join(", ");
+                CliError::error(&format!("Invalid value '{}' for '{}', expected one of {}", value, arg, expected), 1)
+            }
+        }
+    }
+
+    /// Get a passed value which must be one of the given choices
+    pub fn get_choice(&self, arg: &str, choices: &[&str]) -> Result<String, CliError>
+    {
+        self.get_choice_matching(arg, choices, false)
+    }
+
+    /// Get a passed value which must be one of the given choices, ignoring
+    /// case, returning the choice as it is spelled in `choices`
+    pub fn get_choice_ci(&self, arg: &str, choices: &[&str]) -> Result<String, CliError>
+    {
+        self.get_choice_matching(arg, choices, true)
+    }
+
+    /// Get a passed value while checking that it is within the range
+    /// `[min, max]`, returning a CliError if it is not
+    pub fn get_passed_range_checked<T: std::str::FromStr + PartialOrd + std::fmt::Display>(&self, arg: &str, min: T, max: T) -> Result<T, CliError>
+    {
+        let value = self.get_passed_checked::<T>(arg)?;
+
+        if value < min || value > max
+        {
+            return CliError::error(&format!("argument {} value {} is out of range [{}, {}]", arg, value, min, max), 1);
+        }
+
+        Ok(value)
+    }
+}
+
+
+/// Colors which can be used in the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColor
+{
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Purple,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightPurple,
+    BrightCyan,
+    BrightWhite
+}
+
+
+/// Styles which can use used in the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiStyle
+{
+    Bold,
+    Underline,
+    Strikethrough
+}
+
+/// When decorations should be displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode
+{
+    /// Display decorations if the terminal supports them
+    Auto,
+    /// Always display decorations
+    Full,
+    /// Never display decorations
+    None
+}
+
+/// Should decorations be displayed when running in a CI environment
+static FORCE_COLOR_IN_CI: AtomicBool = AtomicBool::new(false);
+
+/// Environment variables set by common CI systems
+const CI_ENVIRONMENT_VARIABLES: [&str; 5] = ["CI", "GITHUB_ACTIONS", "GITLAB_CI", "JENKINS_URL", "TRAVIS"];
+
+/// Check if the program is running in a CI environment
+pub fn detect_ci_environment() -> bool
+{
+    CI_ENVIRONMENT_VARIABLES.iter().any(|var| std::env::var_os(var).is_some())
+}
+
+/// Display decorations even when running in a CI environment
+pub fn force_color_in_ci()
+{
+    let _lock = lock_settings();
+    FORCE_COLOR_IN_CI.store(true, Ordering::Relaxed);
+}
+
+/// Check if decorations can be displayed given a way to look up environment
+/// variables and whether the output is a terminal
+fn color_supported_with<F: Fn(&str) -> Option<String>>(lookup: F, is_terminal: bool) -> bool
+{
+    let is_set = |var: &str| lookup(var).map(|val| !val.is_empty()).unwrap_or(false);
+
+    if lookup("CLICOLOR_FORCE").map(|val| !val.is_empty() && val != "0").unwrap_or(false)
+    {
+        return true;
+    }
+
+    if is_set("NO_COLOR") || lookup("CLICOLOR").map(|val| val == "0").unwrap_or(false)
+    {
+        return false;
+    }
+
+    if CI_ENVIRONMENT_VARIABLES.iter().any(|var| lookup(var).is_some()) && !FORCE_COLOR_IN_CI.load(Ordering::Relaxed)
+    {
+        return false;
+    }
+
+    is_terminal
+}
+
+/// Check if decorations can be displayed, the environment variables are
+/// checked in order of precedence:
+///
+/// 1. `CLICOLOR_FORCE` set to anything other than `0` enables decorations
+/// 2. `NO_COLOR` set to anything disables decorations
+/// 3. `CLICOLOR` set to `0` disables decorations
+/// 4. Running in a CI environment disables decorations (unless
+///    `force_color_in_ci` has been called)
+///
+/// Otherwise decorations are displayed if stdout is a terminal
+pub fn color_supported() -> bool
+{
+    color_supported_with(|var| std::env::var(var).ok(), Stream::Stdout.is_terminal())
+}
+
+/// Global color mode, stored as the discriminant of a ColorMode
+static COLOR_MODE: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+
+/// Set when decorations should be displayed throughout the library
+pub fn set_color_mode(mode: ColorMode)
+{
+    let _lock = lock_settings();
+    COLOR_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Get when decorations should be displayed throughout the library
+pub fn color_mode() -> ColorMode
+{
+    match COLOR_MODE.load(Ordering::Relaxed)
+    {
+        0 => ColorMode::Auto,
+        1 => ColorMode::Full,
+        _ => ColorMode::None
+    }
+}
+
+/// Output streams which can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream
+{
+    Stdout,
+    Stderr
+}
+
+impl Stream
+{
+    /// Check if the stream is a terminal
+    pub fn is_terminal(self) -> bool
+    {
+        use std::io::IsTerminal;
+
+        match self
+        {
+            Stream::Stdout => std::io::stdout().is_terminal(),
+            Stream::Stderr => std::io::stderr().is_terminal()
+        }
+    }
+}
+
+/// Check if decorations should be displayed on a stream given the global
+/// color mode, a way to look up environment variables and whether the stream
+/// is a terminal
+pub fn should_colorize_with<F: Fn(&str) -> Option<String>>(lookup: F, is_terminal: bool) -> bool
+{
+    match color_mode()
+    {
+        ColorMode::Auto => color_supported_with(lookup, is_terminal),
+        ColorMode::Full => true,
+        ColorMode::None => false
+    }
+}
+
+/// Check if decorations should be displayed on a stream, following the global
+/// color mode and, with ColorMode::Auto, the rules of `color_supported` for
+/// that stream
+pub fn should_colorize(stream: Stream) -> bool
+{
+    should_colorize_with(|var| std::env::var(var).ok(), stream.is_terminal())
+}
+
+/// Check if decorations should be displayed on stdout, based on the global
+/// color mode
+pub fn color_enabled() -> bool
+{
+    should_colorize(Stream::Stdout)
+}
+
+/// Can unicode characters (such as box drawing characters) be displayed
+static UNICODE_SUPPORTED: AtomicBool = AtomicBool::new(true);
+
+/// File which reported errors are appended to
+static LOG_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Set once unicode support has been detected or given to `init_with_options`
+static UNICODE_CONFIGURED: AtomicBool = AtomicBool::new(false);
+
+/// Options used to configure the library with `init_with_options`. Settings
+/// given as None are left as they are
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CliOptions<'a>
+{
+    /// Always display decorations regardless of the environment, or decide
+    /// from the environment and terminal when writing if false
+    pub force_color: Option<bool>,
+    /// Display decorations even when running in a CI environment
+    pub force_color_in_ci: Option<bool>,
+    /// Can unicode characters be displayed, or None to detect it from the
+    /// locale if it has not already been detected or given
+    pub unicode: Option<bool>,
+    /// File which reported errors are appended to
+    pub log_file: Option<&'a Path>
+}
+
+/// Configure the library from the environment, detecting whether unicode
+/// characters can be displayed. Settings which have already been configured
+/// are kept, so calling it again has no further effect
+pub fn init()
+{
+    init_with_options(CliOptions::default());
+}
+
+/// Configure the library from the environment, with the given options taking
+/// precedence over what is detected. Only the settings which are given are
+/// changed
+pub fn init_with_options(options: CliOptions)
+{
+    let _lock = lock_settings();
+
+    if let Some(force) = options.force_color_in_ci
+    {
+        FORCE_COLOR_IN_CI.store(force, Ordering::Relaxed);
+    }
+
+    // Without forcing, whether decorations are displayed is decided from the
+    // environment and terminal when writing
+    if let Some(force) = options.force_color
+    {
+        set_color_mode(if force { ColorMode::Full } else { ColorMode::Auto });
+    }
+
+    match options.unicode
+    {
+        Some(unicode) =>
+        {
+            UNICODE_SUPPORTED.store(unicode, Ordering::Relaxed);
+            UNICODE_CONFIGURED.store(true, Ordering::Relaxed);
+        },
+        None =>
+        {
+            if !UNICODE_CONFIGURED.swap(true, Ordering::Relaxed)
+            {
+                UNICODE_SUPPORTED.store(detect_unicode_support(), Ordering::Relaxed);
+            }
+        }
+    }
+
+    if let Some(path) = options.log_file
+    {
+        if let Ok(mut log_file) = LOG_FILE.lock()
+        {
+            *log_file = Some(path.to_path_buf());
+        }
+    }
+}
+
+/// Check if the locale uses UTF-8 from the `LC_ALL`, `LC_CTYPE` and `LANG`
+/// environment variables, the first of which is set takes precedence
+pub fn detect_unicode_support() -> bool
+{
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|val| !val.is_empty());
+
+    match locale
+    {
+        Some(locale) =>
+        {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        },
+        // Windows does not set a locale, but its terminals support unicode
+        None => cfg!(windows)
+    }
+}
+
+/// Check if unicode characters can be displayed, as configured by `init`
+pub fn unicode_supported() -> bool
+{
+    UNICODE_SUPPORTED.load(Ordering::Relaxed)
+}
+
+/// Get the width of the terminal in columns from the `COLUMNS` environment
+/// variable, or None if it is not set
+pub fn terminal_width() -> Option<usize>
+{
+    std::env::var("COLUMNS").ok()?.trim().parse::<usize>().ok().filter(|width| *width > 0)
+}
+
+/// Append a line to the log file, if one has been configured. Failing to
+/// write the log is not reported
+fn log_line(line: &str)
+{
+    use std::io::Write;
+
+    let path = match LOG_FILE.lock()
+    {
+        Ok(log_file) => log_file.clone(),
+        Err(_) => None
+    };
+
+    if let Some(path) = path
+    {
+        if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open(path)
+        {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+/// A snapshot of the global settings of the library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings
+{
+    /// Verbosity level, as set by `set_verbosity`
+    pub verbosity: Verbosity,
+    /// Color mode, as set by `set_color_mode`
+    pub color_mode: ColorMode,
+    /// Default error code, as set by `CliError::set_default_code`
+    pub default_error_code: i32,
+    /// Should decorations be displayed in a CI environment, as set by
+    /// `force_color_in_ci`
+    pub force_color_in_ci: bool,
+    /// Can unicode characters be displayed, as configured by `init`
+    pub unicode: bool,
+    /// File which reported errors are appended to, as configured by `init`
+    pub log_file: Option<PathBuf>
+}
+
+impl Settings
+{
+    /// Take a snapshot of the current global settings
+    pub fn current() -> Settings
+    {
+        Settings
+        {
+            verbosity: verbosity(),
+            color_mode: color_mode(),
+            default_error_code: CliError::default_code(),
+            force_color_in_ci: FORCE_COLOR_IN_CI.load(Ordering::Relaxed),
+            unicode: unicode_supported(),
+            log_file: LOG_FILE.lock().map(|log_file| log_file.clone()).unwrap_or(None)
+        }
+    }
+
+    /// Make these the current global settings
+    pub fn apply(&self)
+    {
+        let _lock = lock_settings();
+
+        set_verbosity(self.verbosity);
+        set_color_mode(self.color_mode);
+        CliError::set_default_code(self.default_error_code);
+        FORCE_COLOR_IN_CI.store(self.force_color_in_ci, Ordering::Relaxed);
+        UNICODE_SUPPORTED.store(self.unicode, Ordering::Relaxed);
+
+        if let Ok(mut log_file) = LOG_FILE.lock()
+        {
+            *log_file = self.log_file.clone();
+        }
+    }
+}
+
+/// Lock on the global settings, which can be taken again by the thread which
+/// holds it so that settings can be changed while it is held
+struct SettingsLock
+{
+    /// Thread holding the lock and how many times it has taken it
+    owner: Mutex<(Option<std::thread::ThreadId>, usize)>,
+    /// Signalled when the lock is released
+    released: Condvar
+}
+
+/// Held while the global settings are changed, or while a `SettingsGuard` is
+/// alive, so that threads do not change the settings underneath each other
+static SETTINGS_LOCK: SettingsLock = SettingsLock
+{
+    owner: Mutex::new((None, 0)),
+    released: Condvar::new()
+};
+
+/// Releases the settings lock when dropped
+#[derive(Debug)]
+struct SettingsLockGuard
+{
+    /// The lock must be released by the thread which took it
+    _not_send: std::marker::PhantomData<*const ()>
+}
+
+impl Drop for SettingsLockGuard
+{
+    fn drop(&mut self)
+    {
+        let mut owner = SETTINGS_LOCK.owner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        owner.1 -= 1;
+
+        if owner.1 == 0
+        {
+            owner.0 = None;
+            SETTINGS_LOCK.released.notify_all();
+        }
+    }
+}
+
+/// Take the settings lock, waiting while another thread holds it
+fn lock_settings() -> SettingsLockGuard
+{
+    let current = std::thread::current().id();
+    let mut owner = SETTINGS_LOCK.owner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    while owner.0.is_some() && owner.0 != Some(current)
+    {
+        owner = SETTINGS_LOCK.released.wait(owner).unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+
+    owner.0 = Some(current);
+    owner.1 += 1;
+
+    SettingsLockGuard
+    {
+        _not_send: std::marker::PhantomData
+    }
+}
+
+/// Restores the global settings to a snapshot when dropped. Other threads
+/// cannot change the settings while it is alive
+#[derive(Debug)]
+pub struct SettingsGuard
+{
+    /// Settings restored when dropped
+    saved: Settings,
+    /// Released after the settings are restored
+    _lock: SettingsLockGuard
+}
+
+impl Drop for SettingsGuard
+{
+    fn drop(&mut self)
+    {
+        self.saved.apply();
+    }
+}
+
+/// Take a snapshot of the global settings, which are restored when the
+/// returned guard is dropped
+pub fn settings() -> SettingsGuard
+{
+    let lock = lock_settings();
+
+    SettingsGuard
+    {
+        saved: Settings::current(),
+        _lock: lock
+    }
+}
+
+/// Run `f` with the given global settings, restoring the previous settings
+/// afterwards (even if `f` panics). Calls from different threads run one at a
+/// time, while calls within `f` are nested
+pub fn with_settings<T, F: FnOnce() -> T>(settings: Settings, f: F) -> T
+{
+    let _guard = crate::settings();
+
+    settings.apply();
+    f()
+}
+
+/// Decorate a line with a color and style if decorations are enabled for the
+/// stream it is written to
+fn decorate_line(msg: &str, color: AnsiColor, style: Option<AnsiStyle>, stream: Stream) -> String
+{
+    if !should_colorize(stream)
+    {
+        return String::from(msg);
+    }
+
+    match style
+    {
+        Some(style) => decorate(String::from(msg), color, style),
+        None => decorate_color(String::from(msg), color)
+    }
+}
+
+/// Print a line to stdout with a color
+pub fn stdout_colored(msg: &str, color: AnsiColor)
+{
+    println!("{}", decorate_line(msg, color, None, Stream::Stdout));
+}
+
+/// Print a line to stderr with a color
+pub fn stderr_colored(msg: &str, color: AnsiColor)
+{
+    eprintln!("{}", decorate_line(msg, color, None, Stream::Stderr));
+}
+
+/// Print a line to stdout with a color and style
+pub fn stdout_styled(msg: &str, color: AnsiColor, style: AnsiStyle)
+{
+    println!("{}", decorate_line(msg, color, Some(style), Stream::Stdout));
+}
+
+/// Print a line to stderr with a color and style
+pub fn stderr_styled(msg: &str, color: AnsiColor, style: AnsiStyle)
+{
+    eprintln!("{}", decorate_line(msg, color, Some(style), Stream::Stderr));
+}
+
+/// Kinds of status line printed by `success`, `info`, `warn` and `error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusLevel
+{
+    Success,
+    Info,
+    Warning,
+    Error
+}
+
+/// Write a status line with an icon (or an ASCII fallback if unicode is not
+/// supported) before the message, with the icon colored if `colorize` is true.
+/// Only error lines are written when running quietly
+pub fn write_status<W: std::io::Write>(writer: &mut W, level: StatusLevel, msg: &str, colorize: bool) -> std::io::Result<()>
+{
+    if level != StatusLevel::Error && verbosity() == Verbosity::Quiet
+    {
+        return Ok(());
+    }
+
+    let (icon, fallback, color) = match level
+    {
+        StatusLevel::Success => ("✓", "+", AnsiColor::Green),
+        StatusLevel::Info => ("ℹ", "i", AnsiColor::Blue),
+        StatusLevel::Warning => ("⚠", "!", AnsiColor::Yellow),
+        StatusLevel::Error => ("✗", "x", AnsiColor::Red)
+    };
+
+    let icon = String::from(if unicode_supported() { icon } else { fallback });
+    let icon = if colorize { decorate(icon, color, AnsiStyle::Bold) } else { icon };
+
+    writeln!(writer, "{} {}", icon, msg)
+}
+
+/// Print a success status line to stdout, unless running quietly
+pub fn success(msg: &str)
+{
+    let _ = write_status(&mut std::io::stdout(), StatusLevel::Success, msg, should_colorize(Stream::Stdout));
+}
+
+/// Print an informational status line to stdout, unless running quietly
+pub fn info(msg: &str)
+{
+    let _ = write_status(&mut std::io::stdout(), StatusLevel::Info, msg, should_colorize(Stream::Stdout));
+}
+
+/// Print a warning status line to stderr, unless running quietly
+pub fn warn(msg: &str)
+{
+    let _ = write_status(&mut std::io::stderr(), StatusLevel::Warning, msg, should_colorize(Stream::Stderr));
+}
+
+/// Print an error status line to stderr
+pub fn error(msg: &str)
+{
+    let _ = write_status(&mut std::io::stderr(), StatusLevel::Error, msg, should_colorize(Stream::Stderr));
+}
+
+/// A piece of a string which may contain ANSI escape sequences
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnsiToken<'a>
+{
+    /// Text which is displayed
+    Text(&'a str),
+    /// Select Graphic Rendition sequence (such as a color or style), with its
+    /// numeric parameters
+    Sgr(Vec<u16>),
+    /// Any other escape sequence (such as cursor movement), in full
+    Other(&'a str)
+}
+
+/// Iterator splitting a string into text and ANSI escape sequences
+pub struct AnsiParser<'a>
+{
+    /// String being parsed
+    s: &'a str,
+    /// Byte offset of the next token
+    pos: usize
+}
+
+impl<'a> AnsiParser<'a>
+{
+    /// Generate a new AnsiParser object for a string
+    pub fn new(s: &'a str) -> Self
+    {
+        Self
+        {
+            s,
+            pos: 0
+        }
+    }
+
+    /// Get the length in bytes of the escape sequence at the start of a string
+    fn sequence_length(rest: &str) -> usize
+    {
+        let bytes = rest.as_bytes();
+
+        match bytes.get(1)
+        {
+            // Control Sequence: parameters and intermediates, then a final byte
+            Some(b'[') =>
+            {
+                match bytes[2..].iter().position(|b| (0x40..=0x7E).contains(b))
+                {
+                    Some(i) => i + 3,
+                    None => bytes.len()
+                }
+            },
+            // Operating System Command: terminated by BEL or ESC followed by a backslash
+            Some(b']') =>
+            {
+                let mut i = 2;
+                while i < bytes.len()
+                {
+                    if bytes[i] == 0x07
+                    {
+                        return i + 1;
+                    }
+                    if bytes[i] == 0x1B && bytes.get(i + 1) == Some(&b'\\')
+                    {
+                        return i + 2;
+                    }
+                    i += 1;
+                }
+                bytes.len()
+            },
+            Some(_) => 1 + rest[1..].chars().next().map(char::len_utf8).unwrap_or(0),
+            None => 1
+        }
+    }
+}
+
+impl<'a> Iterator for AnsiParser<'a>
+{
+    type Item = AnsiToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        let rest = &self.s[self.pos..];
+
+        if rest.is_empty()
+        {
+            return None;
+        }
+
+        if !rest.starts_with('\x1B')
+        {
+            let length = rest.find('\x1B').unwrap_or(rest.len());
+            self.pos += length;
+            return Some(AnsiToken::Text(&rest[..length]));
+        }
+
+        let length = AnsiParser::sequence_length(rest);
+        let sequence = &rest[..length];
+        self.pos += length;
+
+        if sequence.starts_with("\x1B[") && sequence.ends_with('m')
+        {
+            let params = &sequence[2..length - 1];
+
+            if params.chars().all(|c| c.is_ascii_digit() || c == ';')
+            {
+                return Some(AnsiToken::Sgr(params.split(';').filter_map(|param| param.parse::<u16>().ok()).collect()));
+            }
+        }
+
+        Some(AnsiToken::Other(sequence))
+    }
+}
+
+/// Clear all of the decorations from a string
+pub fn clear_decoration(s: String) -> String
+{
+    AnsiParser::new(&s)
+        .filter_map(|token| match token
+        {
+            AnsiToken::Text(text) => Some(text),
+            _ => None
+        })
+        .collect()
+}
+
+/// Resets the decorations of the terminal when dropped, so that a program
+/// exiting part way through a decorated string does not leave the terminal
+/// decorated. Create one at the start of `main` and keep it alive until the
+/// program ends, and call `ColorGuard::install_panic_hook` to also reset the
+/// terminal when panicking
+pub struct ColorGuard
+{
+    /// Writers which the reset is written to, and whether each is a terminal
+    targets: Vec<(Box<dyn std::io::Write + Send>, bool)>
+}
+
+impl ColorGuard
+{
+    /// Generate a new ColorGuard object, which only resets the streams which
+    /// are terminals
+    pub fn new() -> Self
+    {
+        use std::io::IsTerminal;
+
+        let stdout_terminal = std::io::stdout().is_terminal();
+        let stderr_terminal = std::io::stderr().is_terminal();
+
+        Self
+        {
+            targets: vec![(Box::new(std::io::stdout()), stdout_terminal), (Box::new(std::io::stderr()), stderr_terminal)]
+        }
+    }
+
+    /// Generate a new ColorGuard object which resets `writer` rather than
+    /// stdout and stderr, if it is a terminal
+    pub fn for_writer<W: std::io::Write + Send + 'static>(writer: W, is_terminal: bool) -> Self
+    {
+        Self
+        {
+            targets: vec![(Box::new(writer), is_terminal)]
+        }
+    }
+
+    /// Write the reset sequence to a writer if it is a terminal
+    pub fn reset<W: std::io::Write>(writer: &mut W, is_terminal: bool) -> std::io::Result<()>
+    {
+        if is_terminal
+        {
+            write!(writer, "\x1B[0m")?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Install a panic hook which resets the decorations of stderr before the
+    /// existing panic hook runs
+    pub fn install_panic_hook()
+    {
+        use std::io::IsTerminal;
+
+        let previous = std::panic::take_hook();
+
+        std::panic::set_hook(Box::new(move |info|
+        {
+            let _ = ColorGuard::reset(&mut std::io::stderr(), std::io::stderr().is_terminal());
+            previous(info);
+        }));
+    }
+}
+
+impl Default for ColorGuard
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Drop for ColorGuard
+{
+    fn drop(&mut self)
+    {
+        for (writer, is_terminal) in &mut self.targets
+        {
+            let _ = ColorGuard::reset(writer, *is_terminal);
+        }
+    }
+}
+
+/// Get the number of terminal columns taken up by a character
+fn char_width(c: char) -> usize
+{
+    match c as u32
+    {
+        // Combining marks, zero width joiners and variation selectors
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        // East Asian wide and fullwidth characters, and emoji
+        0x1100..=0x115F | 0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF |
+        0x4E00..=0x9FFF | 0xA000..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF |
+        0xFE30..=0xFE4F | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F |
+        0x1F900..=0x1F9FF | 0x20000..=0x3FFFD => 2,
+        _ => 1
+    }
+}
+
+/// Get the number of terminal columns taken up by a string, ignoring any
+/// decorations
+pub fn display_width(s: &str) -> usize
+{
+    AnsiParser::new(s)
+        .map(|token| match token
+        {
+            AnsiToken::Text(text) => text.chars().map(char_width).sum(),
+            _ => 0
+        })
+        .sum()
+}
+
+/// Decorate a string with a color
+pub fn decorate_color(s: String, color: AnsiColor) -> String
+{
+    let start = String::from(match color
+    {
+        AnsiColor::Black => "\x1B[30m",
+        AnsiColor::Red => "\x1B[31m",
+        AnsiColor::Green => "\x1B[32m",
+        AnsiColor::Yellow => "\x1B[33m",
+        AnsiColor::Blue => "\x1B[34m",
+        AnsiColor::Purple => "\x1B[35m",
+        AnsiColor::Cyan => "\x1B[36m",
+        AnsiColor::White => "\x1B[37m",
+        AnsiColor::BrightBlack => "\x1B[90m",
+        AnsiColor::BrightRed => "\x1B[91m",
+        AnsiColor::BrightGreen => "\x1B[92m",
+        AnsiColor::BrightYellow => "\x1B[93m",
+        AnsiColor::BrightBlue => "\x1B[94m",
+        AnsiColor::BrightPurple => "\x1B[95m",
+        AnsiColor::BrightCyan => "\x1B[96m",
+        AnsiColor::BrightWhite => "\x1B[97m"
+    });
+
+    start + &clear_decoration(s) + &String::from("\x1B[0m")
+}
+
+/// Decorate a string with a style
+pub fn decorate_style(s: String, style: AnsiStyle) -> String
+{
+    let start = String::from(match style
+    {
+        AnsiStyle::Bold => "\x1B[1m",
+        AnsiStyle::Strikethrough => "\x1B[9m",
+        AnsiStyle::Underline => "\x1B[4m"
+    });
+
+    start + &clear_decoration(s) + &String::from("\x1B[0m")
+}
+
+/// Decorate with a color and style
+pub fn decorate(s: String, color: AnsiColor, style: AnsiStyle) -> String
+{
+    let start = String::from(match style
+        {
+            AnsiStyle::Bold => "\x1B[1m",
+            AnsiStyle::Strikethrough => "\x1B[9m",
+            AnsiStyle::Underline => "\x1B[4m"
+        });
+    
+    start + &decorate_color(s, color)
+}
+
+/// Decorate with a color and multiple styles
+pub fn decorate_multiple(s: String, color: AnsiColor, styles: Vec<AnsiStyle>) -> String
+{
+    let mut result = decorate_color(s, color);
+
+    for style in &styles
+    {
+        let start = String::from(match style
+            {
+                AnsiStyle::Bold => "\x1B[1m",
+                AnsiStyle::Strikethrough => "\x1B[9m",
+                AnsiStyle::Underline => "\x1B[4m"
+            });
+
+        result = start + &result;
+    }
+
+    result
+}
+
+/// Make text a hyperlink to `url`, which is clickable in terminals which
+/// support hyperlinks and displayed as plain text in other terminals
+pub fn decorate_hyperlink(s: String, url: &str) -> String
+{
+    format!("\x1B]8;;{}\x1B\\{}\x1B]8;;\x1B\\", url, s)
+}
+
+/// Decorate with a color if decorations are enabled for stdout, otherwise the
+/// string is returned borrowed without allocating
+pub fn decorate_color_cow(s: &str, color: AnsiColor) -> std::borrow::Cow<'_, str>
+{
+    if should_colorize(Stream::Stdout)
+    {
+        std::borrow::Cow::Owned(decorate_color(String::from(s), color))
+    }
+    else
+    {
+        std::borrow::Cow::Borrowed(s)
+    }
+}
+
+/// Decorate with a color and style if decorations are enabled for stdout,
+/// otherwise the string is returned borrowed without allocating
+pub fn decorate_cow(s: &str, color: AnsiColor, style: AnsiStyle) -> std::borrow::Cow<'_, str>
+{
+    if should_colorize(Stream::Stdout)
+    {
+        std::borrow::Cow::Owned(decorate(String::from(s), color, style))
+    }
+    else
+    {
+        std::borrow::Cow::Borrowed(s)
+    }
+}
+
+
+/// Horizontal alignment of a column in a GridDisplay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnAlignment
+{
+    Left,
+    Right,
+    Center
+}
+
+/// Vertical alignment of the cells of a row in a GridDisplay, for rows with
+/// cells spanning multiple lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VAlign
+{
+    Top,
+    Middle,
+    Bottom
+}
+
+/// Style of the border drawn around a GridDisplay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle
+{
+    None,
+    Ascii,
+    Unicode
+}
+
+/// Characters used to draw a border
+struct BorderChars
+{
+    horizontal: &'static str,
+    vertical: &'static str,
+    top_left: &'static str,
+    top: &'static str,
+    top_right: &'static str,
+    left: &'static str,
+    cross: &'static str,
+    right: &'static str,
+    bottom_left: &'static str,
+    bottom: &'static str,
+    bottom_right: &'static str
+}
+
+impl BorderStyle
+{
+    /// Get the characters used to draw the border, or None if no border is drawn
+    fn chars(self) -> Option<BorderChars>
+    {
+        match self
+        {
+            BorderStyle::None => None,
+            BorderStyle::Ascii => Some(BorderChars
+            {
+                horizontal: "-",
+                vertical: "|",
+                top_left: "+",
+                top: "+",
+                top_right: "+",
+                left: "+",
+                cross: "+",
+                right: "+",
+                bottom_left: "+",
+                bottom: "+",
+                bottom_right: "+"
+            }),
+            BorderStyle::Unicode => Some(BorderChars
+            {
+                horizontal: "─",
+                vertical: "│",
+                top_left: "┌",
+                top: "┬",
+                top_right: "┐",
+                left: "├",
+                cross: "┼",
+                right: "┤",
+                bottom_left: "└",
+                bottom: "┴",
+                bottom_right: "┘"
+            })
+        }
+    }
+}
+
+/// Strings placed around and between the cells of a rendered row
+struct RowFrame
+{
+    left: String,
+    separator: String,
+    right: String
+}
+
+/// Pad a string with spaces to `width` columns, measuring the string by its
+/// display width so that decorations are not counted
+fn pad_visible(s: &str, width: usize) -> String
+{
+    String::from(s) + &" ".repeat(width.saturating_sub(display_width(s)))
+}
+
+/// Wrap text into lines no wider than `width`, breaking between words where
+/// possible. Existing newlines are kept, the leading indentation of each line
+/// is repeated on the lines it wraps onto and words wider than `width` are
+/// broken across lines
+pub fn wrap(text: &str, width: usize) -> Vec<String>
+{
+    let mut lines = vec![];
+
+    for paragraph in text.split('\n')
+    {
+        let leading = &paragraph[..paragraph.len() - paragraph.trim_start().len()];
+        let width = width.saturating_sub(display_width(leading)).max(1);
+        let first = lines.len();
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace()
+        {
+            let mut word = String::from(word);
+
+            while display_width(&word) > width
+            {
+                if !line.is_empty()
+                {
+                    lines.push(std::mem::take(&mut line));
+                }
+
+                // Take as many characters as fit, but always at least one
+                let mut used = 0;
+                let split = word.char_indices()
+                    .find(|(_, c)| { used += char_width(*c); used > width })
+                    .map(|(i, _)| i)
+                    .filter(|i| *i > 0)
+                    .unwrap_or_else(|| word.chars().next().map(char::len_utf8).unwrap_or(0));
+
+                lines.push(String::from(&word[..split]));
+                word = String::from(&word[split..]);
+            }
+
+            if line.is_empty()
+            {
+                line = word;
+            }
+            else if display_width(&line) + 1 + display_width(&word) <= width
+            {
+                line.push(' ');
+                line += &word;
+            }
+            else
+            {
+                lines.push(std::mem::replace(&mut line, word));
+            }
+        }
+
+        lines.push(line);
+
+        for line in &mut lines[first..]
+        {
+            line.insert_str(0, leading);
+        }
+    }
+
+    lines
+}
+
+/// Indent each non-empty line of text by `spaces` spaces
+pub fn indent(text: &str, spaces: usize) -> String
+{
+    text.split('\n')
+        .map(|line| if line.is_empty() { String::new() } else { " ".repeat(spaces) + line })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Surround text with a box drawn using unicode box characters (or ASCII if
+/// unicode is not supported), sized to fit the longest line of the text, with
+/// the box optionally colored if decorations are enabled for stdout. Lines too
+/// wide for the terminal are wrapped
+pub fn boxed(text: &str, color: Option<AnsiColor>) -> String
+{
+    boxed_with(text, color, terminal_width(), unicode_supported())
+}
+
+/// Surround text with a box as `boxed` does, wrapping lines to fit within
+/// `terminal_width` columns if it is known
+fn boxed_with(text: &str, color: Option<AnsiColor>, terminal_width: Option<usize>, unicode: bool) -> String
+{
+    let border = |s: String| match color.filter(|_| should_colorize(Stream::Stdout))
+    {
+        Some(color) => decorate_color(s, color),
+        None => s
+    };
+
+    let (top_left, top_right, bottom_left, bottom_right, horizontal, vertical) = if unicode
+    {
+        ("┌", "┐", "└", "┘", "─", "│")
+    }
+    else
+    {
+        ("+", "+", "+", "+", "-", "|")
+    };
+
+    // The borders and padding take up four columns
+    let max_width = terminal_width.map(|width| width.saturating_sub(4).max(1));
+
+    let lines: Vec<String> = text.lines().flat_map(|line| match max_width
+    {
+        Some(max_width) if display_width(line) > max_width => wrap(line, max_width),
+        _ => vec![String::from(line)]
+    }).collect();
+
+    let width = lines.iter().map(|line| display_width(line)).max().unwrap_or(0);
+
+    let mut result = border(format!("{}{}{}", top_left, horizontal.repeat(width + 2), top_right)) + "\n";
+
+    for line in &lines
+    {
+        let padding = " ".repeat(width - display_width(line));
+        result += &format!("{} {}{} {}\n", border(String::from(vertical)), line, padding, border(String::from(vertical)));
+    }
+
+    result += &border(format!("{}{}{}", bottom_left, horizontal.repeat(width + 2), bottom_right));
+    result += "\n";
+
+    result
+}
+
+/// Function used to format the value of a cell when rendering
+type CellFormatter = Box<dyn Fn(&str) -> String>;
+
+/// Value stored in the cells covered by a spanning row
+const SPAN_SENTINEL: &str = "\u{0}span";
+
+/// An object to allow data to be displayed in the console as a grid
+pub struct GridDisplay
+{
+    /// Optional Headers for each column
+    headers: Option<Vec<String>>,
+    /// Rows to be displayed
+    rows: Vec<Vec<String>>,
+    /// Alignment of specific columns
+    alignments: HashMap<usize, ColumnAlignment>,
+    /// Should columns containing only numbers be right aligned automatically
+    auto_numeric_align: bool,
+    /// Columns to be rendered, or all columns if None
+    visible_columns: Option<Vec<usize>>,
+    /// Functions used to format the values of specific columns when rendering
+    formatters: HashMap<usize, CellFormatter>,
+    /// Colors of specific columns
+    column_colors: HashMap<usize, AnsiColor>,
+    /// Optional title displayed above the grid
+    title: Option<String>,
+    /// Color of the title
+    title_color: Option<AnsiColor>,
+    /// Alignment of the title relative to the grid
+    title_align: ColumnAlignment,
+    /// When decorations should be kept in the rendered grid
+    color_mode: ColorMode,
+    /// Style of the border drawn around the grid
+    border_style: BorderStyle,
+    /// Color of the border
+    border_color: Option<AnsiColor>,
+    /// Color of the separator between the header and the rows
+    header_separator_color: Option<AnsiColor>,
+    /// Number of rows after which the header is repeated, or 0 to only
+    /// display it once
+    repeat_header: usize,
+    /// Optional summary row displayed below the rows
+    footer: Option<Vec<String>>,
+    /// Vertical alignment of the cells of rows spanning multiple lines
+    vertical_align: VAlign,
+    /// String placed between columns when there is no border, or None to
+    /// separate columns with spaces
+    separator: Option<String>,
+    /// Colors of specific rows
+    row_colors: HashMap<usize, AnsiColor>,
+    /// Styles of specific rows
+    row_styles: HashMap<usize, AnsiStyle>
+}
+
+
+impl GridDisplay
+{
+    /// Generate an empty GridDisplay object
+    pub fn empty() -> GridDisplay
+    {
+        GridDisplay
+        {
+            headers: None,
+            rows: vec![],
+            alignments: HashMap::new(),
+            auto_numeric_align: false,
+            visible_columns: None,
+            formatters: HashMap::new(),
+            column_colors: HashMap::new(),
+            title: None,
+            title_color: None,
+            title_align: ColumnAlignment::Left,
+            color_mode: ColorMode::Auto,
+            border_style: BorderStyle::None,
+            border_color: None,
+            header_separator_color: None,
+            repeat_header: 0,
+            footer: None,
+            vertical_align: VAlign::Top,
+            separator: None,
+            row_colors: HashMap::new(),
+            row_styles: HashMap::new()
+        }
+    }
+
+    /// Generate a GridDisplay object with the given header
+    pub fn new(headers: Vec<String>) -> GridDisplay
+    {
+        GridDisplay
+        {
+            headers: Some(headers),
+            ..GridDisplay::empty()
+        }
+    }
+
+    /// Set the header for a GridDispaly object
+    pub fn set_header(&mut self, headers: Vec<String>)
+    {
+        self.headers = Some(headers);
+    }
+
+    /// Add a row to the GridDisplay
+    pub fn add_row(&mut self, row: Vec<String>)
+    {
+        self.rows.push(row);
+    }
+
+    /// Add a row with every cell decorated with a color, taking precedence
+    /// over column colors
+    pub fn add_row_colored(&mut self, row: Vec<String>, color: AnsiColor)
+    {
+        self.row_colors.insert(self.rows.len(), color);
+        self.rows.push(row);
+    }
+
+    /// Add a row with every cell decorated with a style
+    pub fn add_row_styled(&mut self, row: Vec<String>, style: AnsiStyle)
+    {
+        self.row_styles.insert(self.rows.len(), style);
+        self.rows.push(row);
+    }
+
+    /// Append the rows of another GridDisplay. If both grids have headers they
+    /// must match, and if only the other grid has headers they are used
+    pub fn extend(&mut self, other: GridDisplay) -> Result<(), CliError>
+    {
+        match (&self.headers, other.headers)
+        {
+            (Some(headers), Some(other_headers)) if *headers != other_headers =>
+            {
+                return CliError::error("Cannot extend a grid with a grid with different headers", 1);
+            },
+            (None, other_headers) =>
+            {
+                self.headers = other_headers;
+            },
+            _ => {}
+        }
+
+        // The colors and styles of the other grid's rows move with them
+        let offset = self.rows.len();
+        self.row_colors.extend(other.row_colors.into_iter().map(|(row, color)| (row + offset, color)));
+        self.row_styles.extend(other.row_styles.into_iter().map(|(row, style)| (row + offset, style)));
+
+        self.rows.extend(other.rows);
+
+        Ok(())
+    }
+
+    /// Set the string placed between columns (such as `" | "`) when there is
+    /// no border
+    pub fn set_separator(&mut self, sep: &str)
+    {
+        self.separator = Some(String::from(sep));
+    }
+
+    /// Set the vertical alignment of cells with fewer lines than the tallest
+    /// cell in their row
+    pub fn set_vertical_align(&mut self, alignment: VAlign)
+    {
+        self.vertical_align = alignment;
+    }
+
+    /// Set a summary row (such as totals) displayed below the rows, separated
+    /// from them by a line if bordered or a blank line otherwise. The footer is
+    /// not one of the rows of the grid
+    pub fn set_footer(&mut self, footer: Vec<String>)
+    {
+        self.footer = Some(footer);
+    }
+
+    /// Add a row containing a single value which spans `span` columns, or all
+    /// columns if `span` exceeds the number of columns
+    pub fn add_spanning_row(&mut self, value: String, span: usize)
+    {
+        let columns = self.column_count();
+        let span = if columns > 0 { span.min(columns) } else { 1 };
+
+        let mut row = vec![value];
+        row.resize(span.max(1), String::from(SPAN_SENTINEL));
+
+        self.rows.push(row);
+    }
+
+    /// Swap the rows at indices `a` and `b`
+    pub fn swap_rows(&mut self, a: usize, b: usize) -> Result<(), CliError>
+    {
+        let count = self.rows.len();
+
+        if a >= count || b >= count
+        {
+            return CliError::error(&format!("Cannot swap rows {} and {} of a grid with {} rows", a, b, count), 1);
+        }
+
+        self.rows.swap(a, b);
+        GridDisplay::swap_keys(&mut self.row_colors, a, b);
+        GridDisplay::swap_keys(&mut self.row_styles, a, b);
+
+        Ok(())
+    }
+
+    /// Swap the columns at indices `a` and `b`, including the headers and the
+    /// settings of the columns (such as alignments and colors). Spanning rows
+    /// are not changed
+    pub fn swap_columns(&mut self, a: usize, b: usize) -> Result<(), CliError>
+    {
+        let count = self.column_count();
+
+        if a >= count || b >= count
+        {
+            return CliError::error(&format!("Cannot swap columns {} and {} of a grid with {} columns", a, b, count), 1);
+        }
+
+        let swap_cells = |row: &mut Vec<String>|
+        {
+            // Short rows are padded so the cells have somewhere to move to
+            if row.len() <= a.max(b)
+            {
+                row.resize(a.max(b) + 1, String::new());
+            }
+            row.swap(a, b);
+        };
+
+        if let Some(headers) = &mut self.headers
+        {
+            swap_cells(headers);
+        }
+
+        if let Some(footer) = &mut self.footer
+        {
+            swap_cells(footer);
+        }
+
+        for row in self.rows.iter_mut().filter(|row| !GridDisplay::is_spanning(row))
+        {
+            swap_cells(row);
+        }
+
+        GridDisplay::swap_keys(&mut self.alignments, a, b);
+        GridDisplay::swap_keys(&mut self.formatters, a, b);
+        GridDisplay::swap_keys(&mut self.column_colors, a, b);
+
+        if let Some(visible) = &mut self.visible_columns
+        {
+            for col in visible.iter_mut()
+            {
+                if *col == a { *col = b; } else if *col == b { *col = a; }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the column at index `col`, including its header and settings.
+    /// Columns after it move down by one, and spanning rows are not changed
+    pub fn remove_column(&mut self, col: usize) -> Result<(), CliError>
+    {
+        let count = self.column_count();
+
+        if col >= count
+        {
+            return CliError::error(&format!("Cannot remove column {} of a grid with {} columns", col, count), 1);
+        }
+
+        let remove_cell = |row: &mut Vec<String>|
+        {
+            if col < row.len()
+            {
+                row.remove(col);
+            }
+        };
+
+        if let Some(headers) = &mut self.headers
+        {
+            remove_cell(headers);
+        }
+
+        if let Some(footer) = &mut self.footer
+        {
+            remove_cell(footer);
+        }
+
+        for row in self.rows.iter_mut().filter(|row| !GridDisplay::is_spanning(row))
+        {
+            remove_cell(row);
+        }
+
+        GridDisplay::remove_key(&mut self.alignments, col);
+        GridDisplay::remove_key(&mut self.formatters, col);
+        GridDisplay::remove_key(&mut self.column_colors, col);
+
+        if let Some(visible) = &mut self.visible_columns
+        {
+            visible.retain(|visible_col| *visible_col != col);
+
+            for visible_col in visible.iter_mut().filter(|visible_col| **visible_col > col)
+            {
+                *visible_col -= 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the value stored for column `col`, moving the values of the
+    /// columns after it down by one
+    fn remove_key<V>(map: &mut HashMap<usize, V>, col: usize)
+    {
+        map.remove(&col);
+
+        let mut keys: Vec<usize> = map.keys().filter(|key| **key > col).copied().collect();
+        keys.sort_unstable();
+
+        for key in keys
+        {
+            if let Some(value) = map.remove(&key)
+            {
+                map.insert(key - 1, value);
+            }
+        }
+    }
+
+    /// Swap the values stored for columns `a` and `b`
+    fn swap_keys<V>(map: &mut HashMap<usize, V>, a: usize, b: usize)
+    {
+        let value_a = map.remove(&a);
+        let value_b = map.remove(&b);
+
+        if let Some(value) = value_a
+        {
+            map.insert(b, value);
+        }
+
+        if let Some(value) = value_b
+        {
+            map.insert(a, value);
+        }
+    }
+
+    /// Only render the columns at the given indices, the hidden columns are
+    /// kept in the grid
+    pub fn set_visible_columns(&mut self, indices: &[usize])
+    {
+        self.visible_columns = Some(indices.to_vec());
+    }
+
+    /// Render all of the columns in the grid
+    pub fn show_all_columns(&mut self)
+    {
+        self.visible_columns = None;
+    }
+
+    /// Set a function used to format the values of a column when rendering,
+    /// the values stored in the grid are not changed
+    pub fn set_column_formatter<F: Fn(&str) -> String + 'static>(&mut self, index: usize, f: F)
+    {
+        self.formatters.insert(index, Box::new(f));
+    }
+
+    /// Set the color of every cell in a column, including its header
+    pub fn set_column_color(&mut self, col: usize, color: AnsiColor)
+    {
+        self.column_colors.insert(col, color);
+    }
+
+    /// Set a title to be displayed above the grid
+    pub fn with_title(&mut self, title: &str)
+    {
+        self.title = Some(String::from(title));
+    }
+
+    /// Set the color of the title, the title is also bolded when a color is set
+    pub fn set_title_color(&mut self, color: AnsiColor)
+    {
+        self.title_color = Some(color);
+    }
+
+    /// Set the alignment of the title relative to the width of the grid
+    pub fn set_title_align(&mut self, alignment: ColumnAlignment)
+    {
+        self.title_align = alignment;
+    }
+
+    /// Set when decorations should be kept in the rendered grid, with
+    /// ColorMode::None all decorations are stripped from the cells and with
+    /// ColorMode::Auto (the default) the global color mode is followed
+    pub fn set_color_mode(&mut self, mode: ColorMode)
+    {
+        self.color_mode = mode;
+    }
+
+    /// Set the style of the border drawn around the grid
+    pub fn set_border_style(&mut self, style: BorderStyle)
+    {
+        self.border_style = style;
+    }
+
+    /// Set the color of the border, including the separator between the header
+    /// and the rows unless it is given its own color
+    pub fn set_border_color(&mut self, color: AnsiColor)
+    {
+        self.border_color = Some(color);
+    }
+
+    /// Set the color of the separator between the header and the rows
+    pub fn set_header_separator_color(&mut self, color: AnsiColor)
+    {
+        self.header_separator_color = Some(color);
+    }
+
+    /// Repeat the header (and its separator, if bordered) after every `every`
+    /// rows, for long grids viewed in a pager. A value of 0 only displays the
+    /// header once
+    pub fn set_repeat_header(&mut self, every: usize)
+    {
+        self.repeat_header = every;
+    }
+
+    /// Get the index of the column with the given header, ignoring decorations
+    pub fn column_index(&self, name: &str) -> Option<usize>
+    {
+        self.headers.as_ref()?.iter().position(|header| clear_decoration(header.clone()) == name)
+    }
+
+    /// Resolve a column index which may be negative, counting back from the
+    /// last column (so -1 is the last column), to an index usable with the
+    /// other column methods, failing if there is no such column
+    pub fn resolve_column(&self, index: isize) -> Result<usize, CliError>
+    {
+        let count = self.column_count();
+
+        let resolved = if index < 0
+        {
+            count.checked_sub(index.unsigned_abs())
+        }
+        else
+        {
+            Some(index as usize)
+        };
+
+        match resolved.filter(|resolved| *resolved < count)
+        {
+            Some(resolved) => Ok(resolved),
+            None => CliError::error(&format!("Cannot resolve column {} of a grid with {} columns", index, count), 1)
+        }
+    }
+
+    /// Set the alignment of a column
+    pub fn set_column_alignment(&mut self, col: usize, alignment: ColumnAlignment)
+    {
+        self.alignments.insert(col, alignment);
+    }
+
+    /// Right align a column
+    pub fn align_right(&mut self, col: usize)
+    {
+        self.set_column_alignment(col, ColumnAlignment::Right);
+    }
+
+    /// Right align the given columns, such as columns of numbers
+    pub fn set_right_align(&mut self, cols: &[usize])
+    {
+        for col in cols
+        {
+            self.align_right(*col);
+        }
+    }
+
+    /// Left align a column
+    pub fn align_left(&mut self, col: usize)
+    {
+        self.set_column_alignment(col, ColumnAlignment::Left);
+    }
+
+    /// Center a column
+    pub fn align_center(&mut self, col: usize)
+    {
+        self.set_column_alignment(col, ColumnAlignment::Center);
+    }
+
+    /// Right align every column in which all of the non-header cells are
+    /// numbers, based on the rows currently in the grid
+    pub fn auto_align_numeric(&mut self)
+    {
+        for col in 0..self.column_count()
+        {
+            if self.is_numeric_column(col)
+            {
+                self.align_right(col);
+            }
+        }
+    }
+
+    /// Enable or disable automatically right aligning columns in which every
+    /// non-empty cell is a number
+    pub fn set_auto_numeric_align(&mut self, enabled: bool)
+    {
+        self.auto_numeric_align = enabled;
+    }
+
+    /// Pad a string on the right with spaces to match a given length
+    fn pad(s: String, width: usize) -> String
+    {
+        pad_visible(&s, width)
+    }
+
+    /// Align a string within a given width
+    fn align(s: String, width: usize, alignment: ColumnAlignment) -> String
+    {
+        let space = width - display_width(&s);
+
+        match alignment
+        {
+            ColumnAlignment::Left => GridDisplay::pad(s, width),
+            ColumnAlignment::Right => " ".repeat(space) + &s,
+            ColumnAlignment::Center => GridDisplay::pad(" ".repeat(space / 2) + &s, width)
+        }
+    }
+
+    /// Get the number of columns in the grid
+    fn column_count(&self) -> usize
+    {
+        let header_count = self.headers.as_ref().map(|headers| headers.len()).unwrap_or(0);
+
+        self.rows.iter().map(|row| row.len()).fold(header_count, usize::max)
+    }
+
+    /// Check if a row is a spanning row
+    fn is_spanning(row: &[String]) -> bool
+    {
+        row.len() > 1 && row[1] == SPAN_SENTINEL
+    }
+
+    /// Check if every non-empty cell in a column is a number
+    fn is_numeric_column(&self, col: usize) -> bool
+    {
+        let mut found = false;
+
+        for row in self.rows.iter().filter(|row| !GridDisplay::is_spanning(row))
+        {
+            if let Some(val) = row.get(col)
+            {
+                let val = clear_decoration(val.clone());
+
+                if val.trim().is_empty()
+                {
+                    continue;
+                }
+
+                if val.trim().parse::<f64>().is_err()
+                {
+                    return false;
+                }
+
+                found = true;
+            }
+        }
+
+        found
+    }
+
+    /// Get the alignment to use for each column
+    fn column_alignments(&self, columns: usize) -> Vec<ColumnAlignment>
+    {
+        (0..columns).map(|col|
+        {
+            if let Some(alignment) = self.alignments.get(&col)
+            {
+                *alignment
+            }
+            else if self.auto_numeric_align && self.is_numeric_column(col)
+            {
+                ColumnAlignment::Right
+            }
+            else
+            {
+                ColumnAlignment::Left
+            }
+        }).collect()
+    }
+
+    /// Get the width of a cell, which is the width of its longest line
+    fn cell_width(val: &str) -> usize
+    {
+        val.split('\n').map(display_width).max().unwrap_or(0)
+    }
+
+    /// Render a row of cells, where cells containing newlines are split across
+    /// multiple lines while keeping the other columns aligned
+    fn render_row(row: &[String], max_column_sizes: &[usize], alignments: &[ColumnAlignment], valign: VAlign, frame: &RowFrame) -> String
+    {
+        let mut result = String::from("");
+
+        let mut lines: Vec<Vec<&str>> = row.iter().map(|val| val.split('\n').collect()).collect();
+
+        // Within a border missing cells are filled so the right edge of the
+        // border stays aligned
+        if !frame.left.is_empty() && lines.len() < max_column_sizes.len()
+        {
+            lines.resize(max_column_sizes.len(), vec![""]);
+        }
+
+        let height = lines.iter().map(|cell| cell.len()).max().unwrap_or(1);
+
+        // Number of blank lines above each cell
+        let offsets: Vec<usize> = lines.iter().map(|cell| match valign
+        {
+            VAlign::Top => 0,
+            VAlign::Middle => (height - cell.len()) / 2,
+            VAlign::Bottom => height - cell.len()
+        }).collect();
+
+        for line in 0..height
+        {
+            let cells: Vec<String> = lines.iter().enumerate().map(|(i, cell)|
+            {
+                let part = line.checked_sub(offsets[i]).and_then(|line| cell.get(line)).copied().unwrap_or("");
+                GridDisplay::align(String::from(part), max_column_sizes[i], alignments[i])
+            }).collect();
+
+            result += &frame.left;
+            result += &cells.join(&frame.separator);
+            result += &frame.right;
+            result += "\n";
+        }
+
+        result
+    }
+
+    /// Render a spanning row, padding its value to the width of the columns it
+    /// covers including the gaps between them
+    fn render_spanning_row(row: &[String], max_column_sizes: &[usize], frame: &RowFrame) -> String
+    {
+        let span = row.len().min(max_column_sizes.len());
+        let width = GridDisplay::inner_width(&max_column_sizes[..span], frame);
+
+        let mut cells = vec![if display_width(&row[0]) < width { GridDisplay::pad(row[0].clone(), width) } else { row[0].clone() }];
+
+        // Within a border the columns which are not covered are filled so the
+        // right edge of the border stays aligned
+        if !frame.left.is_empty()
+        {
+            cells.extend(max_column_sizes[span..].iter().map(|size| " ".repeat(*size)));
+        }
+
+        frame.left.clone() + &cells.join(&frame.separator) + &frame.right + "\n"
+    }
+
+    /// Widen the last column covered by each spanning row whose value is wider
+    /// than the columns it spans, so the value fits within the grid
+    fn fit_spanning_rows(max_column_sizes: &mut Vec<usize>, rows: &[Vec<String>], frame: &RowFrame)
+    {
+        for row in rows.iter().filter(|row| GridDisplay::is_spanning(row))
+        {
+            // A grid of only spanning rows has a single column
+            if max_column_sizes.is_empty()
+            {
+                max_column_sizes.push(0);
+            }
+
+            let span = row.len().min(max_column_sizes.len());
+            let width = GridDisplay::inner_width(&max_column_sizes[..span], frame);
+            let needed = GridDisplay::cell_width(&row[0]);
+
+            if needed > width
+            {
+                max_column_sizes[span - 1] += needed - width;
+            }
+        }
+    }
+
+    /// Get the width of the cells of a row including the separators between
+    /// them, but not the left and right of the frame
+    fn inner_width(max_column_sizes: &[usize], frame: &RowFrame) -> usize
+    {
+        max_column_sizes.iter().sum::<usize>() + display_width(&frame.separator) * max_column_sizes.len().saturating_sub(1)
+    }
+
+    /// Decorate part of the border with a color, if one is given
+    fn color_border(part: &str, color: Option<AnsiColor>) -> String
+    {
+        match color
+        {
+            Some(color) => decorate_color(String::from(part), color),
+            None => String::from(part)
+        }
+    }
+
+    /// Get the strings placed around and between the cells of each row
+    fn row_frame(&self) -> RowFrame
+    {
+        match self.border_style.chars()
+        {
+            Some(chars) =>
+            {
+                let vertical = GridDisplay::color_border(chars.vertical, self.border_color);
+
+                RowFrame
+                {
+                    left: format!("{} ", vertical),
+                    separator: format!(" {} ", vertical),
+                    right: format!(" {}", vertical)
+                }
+            },
+            None => match &self.separator
+            {
+                Some(separator) => RowFrame
+                {
+                    left: String::new(),
+                    separator: separator.clone(),
+                    right: String::new()
+                },
+                None => RowFrame
+                {
+                    left: String::new(),
+                    separator: String::from("  "),
+                    right: String::from("  ")
+                }
+            }
+        }
+    }
+
+    /// Render a horizontal line of the border between the given corners, with
+    /// a junction where each gap between columns meets the line
+    fn render_border_line(left: &str, junction: &str, right: &str, horizontal: &str, max_column_sizes: &[usize], color: Option<AnsiColor>) -> String
+    {
+        // Each segment covers a column and the space either side of it
+        let segments: Vec<String> = max_column_sizes.iter().map(|size| horizontal.repeat(size + 2)).collect();
+
+        GridDisplay::color_border(&format!("{}{}{}", left, segments.join(junction), right), color) + "\n"
+    }
+
+    /// Get the indices of the columns to be rendered
+    fn visible_column_indices(&self) -> Vec<usize>
+    {
+        (0..self.column_count())
+            .filter(|col| self.visible_columns.as_ref().map(|visible| visible.contains(col)).unwrap_or(true))
+            .collect()
+    }
+
+    /// Apply the column formatters to a row
+    fn format_row(&self, row: &[String]) -> Vec<String>
+    {
+        if GridDisplay::is_spanning(row)
+        {
+            return row.to_vec();
+        }
+
+        row.iter().enumerate().map(|(i, val)|
+        {
+            match self.formatters.get(&i)
+            {
+                Some(formatter) => formatter(val),
+                None => val.clone()
+            }
+        }).collect()
+    }
+
+    /// Get the cells of a row which are in the given columns
+    fn project_row(row: &[String], columns: &[usize]) -> Vec<String>
+    {
+        if GridDisplay::is_spanning(row)
+        {
+            // A spanning row spans the visible columns it covered, and has no
+            // cells when none of them are visible
+            let span = columns.iter().filter(|col| **col < row.len()).count();
+
+            if span == 0
+            {
+                return vec![];
+            }
+
+            let mut projected = vec![row[0].clone()];
+            projected.resize(span, String::from(SPAN_SENTINEL));
+            projected
+        }
+        else
+        {
+            columns.iter().filter_map(|col| row.get(*col).cloned()).collect()
+        }
+    }
+
+    /// Decorate each line of a cell with a color
+    fn color_cell(val: &str, color: AnsiColor) -> String
+    {
+        val.split('\n')
+            .map(|line| if line.is_empty() || line == SPAN_SENTINEL { String::from(line) } else { decorate_color(String::from(line), color) })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Decorate each line of a cell with a style, keeping any colors
+    fn style_cell(val: &str, style: AnsiStyle) -> String
+    {
+        let start = match style
+        {
+            AnsiStyle::Bold => "\x1B[1m",
+            AnsiStyle::Strikethrough => "\x1B[9m",
+            AnsiStyle::Underline => "\x1B[4m"
+        };
+
+        val.split('\n')
+            .map(|line| if line.is_empty() || line == SPAN_SENTINEL { String::from(line) } else { format!("{}{}\x1B[0m", start, line) })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Apply the column colors to a row
+    fn color_columns(&self, row: Vec<String>) -> Vec<String>
+    {
+        row.into_iter().enumerate().map(|(i, val)|
+        {
+            match self.column_colors.get(&i)
+            {
+                Some(color) => GridDisplay::color_cell(&val, *color),
+                None => val
+            }
+        }).collect()
+    }
+
+    /// Render the GridDisplay to a string
+    pub fn render(&self) -> String
+    {
+        self.render_internal(&|_, _| None)
+    }
+
+    /// Render the GridDisplay to a string, coloring every cell in a row with
+    /// the color returned by calling `f` with the index and cells of the row
+    pub fn render_with_row_colors<F: Fn(usize, &[String]) -> Option<AnsiColor>>(&self, f: F) -> String
+    {
+        self.render_internal(&f)
+    }
+
+    /// Get the headers and rows as they are rendered, formatted, colored and
+    /// limited to the visible columns
+    fn prepare_rows(&self, row_color: &dyn Fn(usize, &[String]) -> Option<AnsiColor>) -> (Option<Vec<String>>, Vec<Vec<String>>)
+    {
+        let columns = self.visible_column_indices();
+        let headers = self.headers.as_ref().map(|headers| GridDisplay::project_row(&self.color_columns(headers.clone()), &columns));
+        let rows: Vec<Vec<String>> = self.rows.iter().enumerate().map(|(i, row)|
+        {
+            let cells = if GridDisplay::is_spanning(row) { &row[..1] } else { &row[..] };
+            let formatted = self.format_row(row);
+
+            // Row colors take precedence over column colors
+            let colored: Vec<String> = match row_color(i, cells).or_else(|| self.row_colors.get(&i).copied())
+            {
+                Some(color) => formatted.iter().map(|val| GridDisplay::color_cell(val, color)).collect(),
+                None if GridDisplay::is_spanning(row) => formatted,
+                None => self.color_columns(formatted)
+            };
+
+            let styled = match self.row_styles.get(&i)
+            {
+                Some(style) => colored.iter().map(|val| GridDisplay::style_cell(val, *style)).collect(),
+                None => colored
+            };
+
+            GridDisplay::project_row(&styled, &columns)
+        }).collect();
+
+        (headers, rows)
+    }
+
+    /// Get the footer as it is rendered, formatted, colored and limited to the
+    /// visible columns
+    fn prepare_footer(&self) -> Option<Vec<String>>
+    {
+        let columns = self.visible_column_indices();
+
+        self.footer.as_ref().map(|footer| GridDisplay::project_row(&self.color_columns(self.format_row(footer)), &columns))
+    }
+
+    /// Get the width of each rendered column, the widest cell in the column
+    fn max_column_sizes(headers: &Option<Vec<String>>, rows: &[Vec<String>], footer: Option<&Vec<String>>) -> Vec<usize>
+    {
+        let mut max_column_sizes: Vec<usize> = vec![];
+
+        match &headers
+        {
+            Some(headers) =>
+            {
+                for header in headers
+                {
+                    max_column_sizes.push(GridDisplay::cell_width(header));
+                }
+            },
+            None => 
+            {
+                if !rows.is_empty() && !GridDisplay::is_spanning(&rows[0])
+                {
+                    for val in &rows[0]
+                    {
+                        max_column_sizes.push(GridDisplay::cell_width(val));
+                    }
+                }
+            }
+        }
+
+        for row in rows.iter().chain(footer).filter(|row| !GridDisplay::is_spanning(row))
+        {
+            for (i, val) in row.iter().enumerate()
+            {
+                if i >= max_column_sizes.len()
+                {
+                    max_column_sizes.push(GridDisplay::cell_width(val));
+                }
+                else if GridDisplay::cell_width(val) > max_column_sizes[i]
+                {
+                    max_column_sizes[i] = GridDisplay::cell_width(val);
+                }
+            }
+        }
+
+        max_column_sizes
+    }
+
+    /// Get the width of a rendered row, including the border and the gaps
+    /// between columns, to check if the grid fits in the terminal before
+    /// rendering it
+    pub fn total_rendered_width(&self) -> usize
+    {
+        let (headers, rows) = self.prepare_rows(&|_, _| None);
+        let footer = self.prepare_footer();
+        let frame = self.row_frame();
+        let mut max_column_sizes = GridDisplay::max_column_sizes(&headers, &rows, footer.as_ref());
+        GridDisplay::fit_spanning_rows(&mut max_column_sizes, &rows, &frame);
+
+        display_width(&frame.left) + GridDisplay::inner_width(&max_column_sizes, &frame) + display_width(&frame.right)
+    }
+
+    /// Render the GridDisplay to a string, coloring rows with `row_color`
+    fn render_internal(&self, row_color: &dyn Fn(usize, &[String]) -> Option<AnsiColor>) -> String
+    {
+        let mut result = String::from("");
+
+        let columns = self.visible_column_indices();
+        let (headers, rows) = self.prepare_rows(row_color);
+        let footer = self.prepare_footer();
+        let frame = self.row_frame();
+        let mut max_column_sizes = GridDisplay::max_column_sizes(&headers, &rows, footer.as_ref());
+        GridDisplay::fit_spanning_rows(&mut max_column_sizes, &rows, &frame);
+
+        let all_alignments = self.column_alignments(self.column_count());
+        let alignments: Vec<ColumnAlignment> = columns.iter().map(|col| all_alignments[*col]).collect();
+
+        let inner_width = GridDisplay::inner_width(&max_column_sizes, &frame);
+        let border = self.border_style.chars();
+
+        if let Some(title) = &self.title
+        {
+            let width = match border
+            {
+                Some(_) => inner_width + display_width(&frame.left) + display_width(&frame.right),
+                None => inner_width
+            };
+            let title = match self.title_color
+            {
+                Some(color) => decorate(title.clone(), color, AnsiStyle::Bold),
+                None => title.clone()
+            };
+
+            if display_width(&title) < width
+            {
+                result += GridDisplay::align(title, width, self.title_align).trim_end();
+            }
+            else
+            {
+                result += &title;
+            }
+            result += "\n";
+        }
+
+        if let Some(chars) = &border
+        {
+            result += &GridDisplay::render_border_line(chars.top_left, chars.top, chars.top_right, chars.horizontal, &max_column_sizes, self.border_color);
+        }
+
+        let rendered_header = headers.as_ref().map(|headers|
+        {
+            let mut header = GridDisplay::render_row(headers, &max_column_sizes, &alignments, self.vertical_align, &frame);
+
+            if let Some(chars) = &border
+            {
+                let color = self.header_separator_color.or(self.border_color);
+                header += &GridDisplay::render_border_line(chars.left, chars.cross, chars.right, chars.horizontal, &max_column_sizes, color);
+            }
+
+            header
+        });
+
+        if let Some(header) = &rendered_header
+        {
+            result += header;
+        }
+
+        for (i, row) in rows.iter().enumerate()
+        {
+            if let Some(header) = rendered_header.as_ref().filter(|_| self.repeat_header > 0 && i > 0 && i % self.repeat_header == 0)
+            {
+                // Within a border the repeated header is separated from the
+                // rows above it
+                if let Some(chars) = &border
+                {
+                    result += &GridDisplay::render_border_line(chars.left, chars.cross, chars.right, chars.horizontal, &max_column_sizes, self.border_color);
+                }
+
+                result += header;
+            }
+
+            if GridDisplay::is_spanning(row)
+            {
+                result += &GridDisplay::render_spanning_row(row, &max_column_sizes, &frame);
+            }
+            else
+            {
+                result += &GridDisplay::render_row(row, &max_column_sizes, &alignments, self.vertical_align, &frame);
+            }
+        }
+
+        if let Some(footer) = &footer
+        {
+            match &border
+            {
+                Some(chars) => result += &GridDisplay::render_border_line(chars.left, chars.cross, chars.right, chars.horizontal, &max_column_sizes, self.border_color),
+                None => result += "\n"
+            }
+
+            result += &GridDisplay::render_row(footer, &max_column_sizes, &alignments, self.vertical_align, &frame);
+        }
+
+        if let Some(chars) = &border
+        {
+            result += &GridDisplay::render_border_line(chars.bottom_left, chars.bottom, chars.bottom_right, chars.horizontal, &max_column_sizes, self.border_color);
+        }
+
+        match self.color_mode
+        {
+            ColorMode::Full => result,
+            ColorMode::None => clear_decoration(result),
+            ColorMode::Auto => if should_colorize(Stream::Stdout) { result } else { clear_decoration(result) }
+        }
+    }
+
+    /// Render a two column GridDisplay as `  key: value` lines with the keys
+    /// padded to the same width and the headers omitted. Grids which do not
+    /// have exactly two columns are rendered normally
+    pub fn render_as_key_value(&self) -> String
+    {
+        if self.column_count() != 2
+        {
+            return self.render();
+        }
+
+        let rows: Vec<Vec<String>> = self.rows.iter().map(|row| self.format_row(row)).collect();
+        let key_width = rows.iter()
+            .filter(|row| row.len() == 2 && !GridDisplay::is_spanning(row))
+            .map(|row| GridDisplay::cell_width(&row[0]))
+            .max()
+            .unwrap_or(0);
+
+        let mut result = String::from("");
+
+        for row in &rows
+        {
+            if GridDisplay::is_spanning(row) || row.len() < 2
+            {
+                result += &format!("  {}\n", row.first().map(String::as_str).unwrap_or(""));
+                continue;
+            }
+
+            // Continuation lines of the value are aligned with its first line
+            let indent = format!("\n{}", " ".repeat(key_width + 4));
+            result += &format!("  {}: {}\n", GridDisplay::pad(row[0].clone(), key_width), row[1].replace('\n', &indent));
+        }
+
+        result
+    }
+
+    /// Render the GridDisplay as delimited text, with fields separated by `sep`
+    /// and fields containing the separator, the quote or a newline wrapped in
+    /// `quote` (with quotes inside doubled). Decorations are removed and all
+    /// columns are included
+    pub fn render_delimited(&self, sep: char, quote: char) -> String
+    {
+        self.render_fields(sep, &|val|
+        {
+            if val.contains(sep) || val.contains(quote) || val.contains('\n') || val.contains('\r')
+            {
+                let doubled = val.replace(quote, &format!("{}{}", quote, quote));
+                format!("{}{}{}", quote, doubled, quote)
+            }
+            else
+            {
+                val
+            }
+        })
+    }
+
+    /// Render the headers and rows with fields separated by `sep`, each field
+    /// having its decorations removed and then passed through `escape`
+    fn render_fields(&self, sep: char, escape: &dyn Fn(String) -> String) -> String
+    {
+        let mut result = String::from("");
+
+        for row in self.headers.iter().chain(self.rows.iter())
+        {
+            result += &row.iter()
+                .map(|val| escape(if val == SPAN_SENTINEL { String::new() } else { clear_decoration(val.clone()) }))
+                .collect::<Vec<String>>()
+                .join(&sep.to_string());
+            result += "\n";
+        }
+
+        result
+    }
+
+    /// Render the GridDisplay as comma separated values
+    pub fn render_csv(&self) -> String
+    {
+        self.render_delimited(',', '"')
+    }
+
+    /// Render the GridDisplay as tab separated values, in the same unquoted
+    /// format as `to_tsv`. Use `render_delimited('\t', '"')` for quoted fields
+    pub fn render_tsv(&self) -> String
+    {
+        self.to_tsv()
+    }
+
+    /// Render the GridDisplay as tab separated values without quoting, for
+    /// tools such as `cut` and `awk` which do not understand quotes. Instead
+    /// tabs, newlines, carriage returns and backslashes inside cells are
+    /// escaped as `\t`, `\n`, `\r` and `\\`. Decorations are removed and all
+    /// columns are included
+    pub fn to_tsv(&self) -> String
+    {
+        self.render_fields('\t', &|val|
+        {
+            val.replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+        })
+    }
+
+    /// Display the GridDisplay object
+    pub fn display(&self)
+    {
+        print!("{}", self.render());
+    }
+
+    /// Display the GridDisplay object, returning the number of rows displayed
+    /// (not including the headers)
+    pub fn display_counted(&self) -> usize
+    {
+        self.display();
+        self.rows.len()
+    }
+}
+
+/// A grid which writes each row to a writer as soon as it is added, using
+/// column widths fixed up front rather than computed from all of the rows
+pub struct StreamingGridDisplay<W: std::io::Write>
+{
+    /// Writer the rows are written to
+    writer: W,
+    /// Width of each column
+    widths: Vec<usize>
+}
+
+impl<W: std::io::Write> StreamingGridDisplay<W>
+{
+    /// Generate a new StreamingGridDisplay object with the given column widths
+    pub fn new(writer: W, widths: Vec<usize>) -> Self
+    {
+        Self
+        {
+            writer,
+            widths
+        }
+    }
+
+    /// Generate a new StreamingGridDisplay object with column widths taken from
+    /// the headers, writing the headers immediately
+    pub fn with_headers(writer: W, headers: Vec<String>) -> std::io::Result<Self>
+    {
+        let widths = headers.iter().map(|header| display_width(header)).collect();
+        let mut grid = StreamingGridDisplay::new(writer, widths);
+
+        grid.add_row(headers)?;
+
+        Ok(grid)
+    }
+
+    /// Fit a value to a width, truncating it (and removing its decorations) if
+    /// it is too long, and padding it if it is too short
+    fn fit(val: &str, width: usize) -> String
+    {
+        let val = val.replace('\n', " ");
+
+        if display_width(&val) <= width
+        {
+            return GridDisplay::pad(val, width);
+        }
+
+        let mut result = String::new();
+
+        for c in clear_decoration(val).chars()
+        {
+            if display_width(&result) + char_width(c) > width
+            {
+                break;
+            }
+            result.push(c);
+        }
+
+        GridDisplay::pad(result, width)
+    }
+
+    /// Write a row immediately, cells beyond the number of columns are dropped
+    pub fn add_row(&mut self, row: Vec<String>) -> std::io::Result<()>
+    {
+        let mut line = String::from("");
+
+        for (val, width) in row.iter().zip(self.widths.iter())
+        {
+            line += &StreamingGridDisplay::<W>::fit(val, *width);
+            line += "  ";
+        }
+
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()
+    }
+
+    /// Get the writer back from the StreamingGridDisplay
+    pub fn into_inner(self) -> W
+    {
+        self.writer
+    }
+}
+
+/// Render a table with optional headers without constructing a GridDisplay
+pub fn format_table(headers: Option<Vec<&str>>, rows: Vec<Vec<&str>>) -> String
+{
+    let to_strings = |row: Vec<&str>| row.into_iter().map(String::from).collect::<Vec<String>>();
+
+    let mut grid = match headers
+    {
+        Some(headers) => GridDisplay::new(to_strings(headers)),
+        None => GridDisplay::empty()
+    };
+
+    for row in rows
+    {
+        grid.add_row(to_strings(row));
+    }
+
+    grid.render()
+}
+
+/// Print a table without headers, with each cell displayed using its Display
+/// implementation
+pub fn print_table<T: std::fmt::Display>(rows: impl IntoIterator<Item = Vec<T>>)
+{
+    let mut grid = GridDisplay::empty();
+
+    for row in rows
+    {
+        grid.add_row(row.iter().map(|val| val.to_string()).collect());
+    }
+
+    grid.display();
+}
+
+/// Print a table with headers, with each cell displayed using its Display
+/// implementation
+pub fn print_table_with_headers<T: std::fmt::Display>(headers: Vec<String>, rows: impl IntoIterator<Item = Vec<T>>)
+{
+    let mut grid = GridDisplay::new(headers);
+
+    for row in rows
+    {
+        grid.add_row(row.iter().map(|val| val.to_string()).collect());
+    }
+
+    grid.display();
+}
+
+/// Options for formatting numbers with `format_number`
+#[derive(Debug, Clone, Copy)]
+pub struct NumberFormat
+{
+    /// Separator placed between groups of three digits
+    separator: Option<char>,
+    /// Number of digits after the decimal point
+    precision: Option<usize>
+}
+
+impl NumberFormat
+{
+    /// Generate a new NumberFormat object which groups digits with commas and
+    /// does not round
+    pub fn new() -> Self
+    {
+        Self
+        {
+            separator: Some(','),
+            precision: None
+        }
+    }
+
+    /// Set the separator placed between groups of three digits, or None to not
+    /// group digits
+    pub fn with_separator(mut self, separator: Option<char>) -> Self
+    {
+        self.separator = separator;
+        self
+    }
+
+    /// Set the number of digits after the decimal point, rounding the number
+    pub fn with_precision(mut self, precision: usize) -> Self
+    {
+        self.precision = Some(precision);
+        self
+    }
+}
+
+impl Default for NumberFormat
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Format a number with grouped digits (such as 1,234,567) and a fixed number
+/// of digits after the decimal point
+pub fn format_number(n: f64, format: &NumberFormat) -> String
+{
+    let formatted = match format.precision
+    {
+        Some(precision) => format!("{:.*}", precision, n),
+        None => format!("{}", n)
+    };
+
+    let separator = match format.separator
+    {
+        Some(separator) => separator,
+        None => return formatted
+    };
+
+    let (sign, unsigned) = match formatted.strip_prefix('-')
+    {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", formatted.as_str())
+    };
+
+    let (integer, fraction) = match unsigned.find('.')
+    {
+        Some(i) => unsigned.split_at(i),
+        None => (unsigned, "")
+    };
+
+    let mut grouped = String::new();
+
+    for (i, c) in integer.chars().enumerate()
+    {
+        if i > 0 && (integer.len() - i) % 3 == 0
+        {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+
+    format!("{}{}{}", sign, grouped, fraction)
+}
+
+/// Help Option Entry
+#[derive(Debug, Clone)]
+pub struct OptionEntry
+{
+    /// Short name
+    short: String,
+    /// Long name
+    long: String,
+    /// Extra Info
+    extra: String,
+    /// Description
+    description: String
+}
+
+impl OptionEntry
+{
+    /// Generate a new OptionEntry object
+    pub fn new(short: &str, long: &str, extra: &str, description: &str) -> Self
+    {
+        Self
+        {
+            short: String::from(short),
+            long: String::from(long),
+            extra: String::from(extra),
+            description: String::from(description)
+        }
+    }
+
+    /// Generate a new OptionEntry object for an option with only a long name
+    pub fn from_long_only(long: &str, description: &str) -> Self
+    {
+        Self::new("", long, "", description)
+    }
+
+    /// Generate a new OptionEntry object for an option with only a short name
+    pub fn from_short_only(short: &str, description: &str) -> Self
+    {
+        Self::new(short, "", "", description)
+    }
+
+    /// Get the short and long parts of the names of the option, as displayed
+    fn name_parts(&self) -> (String, String)
+    {
+        let short_part = 
+        if !self.short.is_empty()
+        {
+            if !self.long.is_empty()
+            {
+                format!("-{},", self.short)
+            }
+            else
+            {
+                format!("-{}", self.short)
+            }
+        }
+        else
+        {
+            String::new()
+        };
+
+        let long_part = 
+        if !self.long.is_empty()
+        {
+            format!("--{} {}", pad_visible(&self.long, 10), self.extra)
+        }
+        else
+        {
+            format!("            {}", self.extra)
+        };
+
+        (short_part, long_part)
+    }
+
+    /// Get the names and extra info of the option, as displayed before its
+    /// description
+    fn names(&self) -> String
+    {
+        let (short_part, long_part) = self.name_parts();
+
+        String::from((pad_visible(&short_part, 4) + &long_part).trim_end())
+    }
+
+    /// Check if a flag (such as `-v` or `--verbose`) refers to this option
+    fn matches(&self, flag: &str) -> bool
+    {
+        (!self.short.is_empty() && flag == format!("-{}", self.short)) ||
+        (!self.long.is_empty() && flag == format!("--{}", self.long))
+    }
+
+    /// Get the name of the option as it would be passed on the command line,
+    /// preferring the long form
+    fn name(&self) -> String
+    {
+        if !self.long.is_empty()
+        {
+            format!("--{}", self.long)
+        }
+        else
+        {
+            format!("-{}", self.short)
+        }
+    }
+}
+
+impl std::fmt::Display for OptionEntry
+{
+    /// Format the entry, a width (such as `{:40}`) sets the width of the
+    /// column of names so that descriptions can be aligned across entries
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let (short_part, long_part) = self.name_parts();
+
+        // Names are padded by display width so that decorated names align
+        match f.width()
+        {
+            Some(width) => write!(f, "  {} {}", pad_visible(&self.names(), width), self.description),
+            None => write!(f, "  {}{} {}", pad_visible(&short_part, 4), pad_visible(&long_part, 27), self.description)
+        }
+    }
+}
+
+/// Generate a HelpDisplay object from the name, description and version in
+/// the Cargo.toml of the calling crate
+///
+/// This is a macro rather than a function so that the metadata is read from
+/// the crate using the library rather than the library itself
+#[macro_export]
+macro_rules! help_from_cargo_metadata
+{
+    () =>
+    {
+        $crate::HelpDisplay::new_with_version(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_DESCRIPTION"), env!("CARGO_PKG_VERSION"))
+    };
+}
+
+/// Narrowest width the help is wrapped to
+const MIN_HELP_WIDTH: usize = 40;
+
+/// Help Display
+#[derive(Debug, Clone)]
+pub struct HelpDisplay
+{
+    /// Usage
+    usage: String,
+    /// Description
+    description: String,
+    /// Entries
+    entries: Vec<OptionEntry>,
+    /// Subcommands and their descriptions
+    commands: Vec<(String, String)>,
+    /// Version
+    version: Option<String>,
+    /// Should descriptions be aligned to the widest name across all sections
+    global_alignment: bool
+}
+
+impl HelpDisplay
+{
+    /// Generate a new HelpDisplay object from the usage string and the description
+    /// string
+    pub fn new(usage: &str, description: &str) -> Self
+    {
+        Self
+        {
+            usage: String::from(usage),
+            description: String::from(description),
+            entries: vec![],
+            commands: vec![],
+            version: None,
+            global_alignment: false
+        }
+    }
+
+    /// Generate a new HelpDisplay object from the usage string, the description
+    /// string and the version string
+    pub fn new_with_version(usage: &str, description: &str, version: &str) -> Self
+    {
+        HelpDisplay::new(usage, description).with_version(version)
+    }
+
+    /// Set the version
+    pub fn with_version(mut self, version: &str) -> Self
+    {
+        self.version = Some(String::from(version));
+        self
+    }
+
+    /// Get the version, if one has been set
+    pub fn version(&self) -> Option<&str>
+    {
+        self.version.as_deref()
+    }
+
+    /// Print the help to stdout and exit successfully, for handling `--help`
+    pub fn print_and_exit(&self) -> !
+    {
+        print!("{}", self);
+        std::process::exit(0);
+    }
+
+    /// Print the help and exit if `--help` or `-h` was given, then print the
+    /// version and exit if `--version` or `-V` was given
+    pub fn auto_handle(&self, args: &Arguments)
+    {
+        let given = |flag: &str| args.args.iter().any(|arg| arg == flag);
+
+        if given("--help") || given("-h")
+        {
+            self.print_and_exit();
+        }
+
+        if given("--version") || given("-V")
+        {
+            self.print_version_and_exit();
+        }
+    }
+
+    /// Print the version to stdout, or `unknown` if no version has been set,
+    /// and exit successfully, for handling `--version`
+    pub fn print_version_and_exit(&self) -> !
+    {
+        println!("{}", self.version().unwrap_or("unknown"));
+        std::process::exit(0);
+    }
+
+    /// Add another command line option
+    pub fn add_option(&mut self, entry: OptionEntry)
+    {
+        self.entries.push(entry);
+    }
+
+    /// Sort the options alphabetically by their long names, using the short
+    /// name for options without a long name. Options are otherwise displayed
+    /// in the order they were added
+    pub fn sort_entries(&mut self)
+    {
+        self.entries.sort_by_key(|entry|
+        {
+            if entry.long.is_empty() { entry.short.to_lowercase() } else { entry.long.to_lowercase() }
+        });
+    }
+
+    /// Add a subcommand, listed with its description in a commands section
+    pub fn add_command(&mut self, name: &str, description: &str)
+    {
+        self.commands.push((String::from(name), String::from(description)));
+    }
+
+    /// Align the descriptions of the commands and options to the widest name
+    /// across all sections, rather than a fixed column
+    pub fn set_global_alignment(&mut self, enabled: bool)
+    {
+        self.global_alignment = enabled;
+    }
+
+    /// Get the width of the widest command or option name, including the extra
+    /// info of options
+    pub fn max_option_name_width(&self) -> usize
+    {
+        let option_width = self.entries.iter().map(|entry| display_width(&entry.names())).max().unwrap_or(0);
+        let command_width = self.commands.iter().map(|(name, _)| display_width(name)).max().unwrap_or(0);
+
+        option_width.max(command_width)
+    }
+
+    /// Render a command or option, with its description aligned after
+    /// `name_width` columns and wrapped to `width`. If the description would
+    /// be too narrow it is placed on the lines below the name, which is never
+    /// wrapped so that its columns stay aligned
+    fn render_item(name: &str, description: &str, name_width: usize, width: usize) -> String
+    {
+        let column = name_width + 3;
+
+        if width >= column + 20 && display_width(name) <= name_width
+        {
+            let lines = wrap(description, width - column);
+            let mut result = format!("  {} {}\n", pad_visible(name, name_width), lines[0]);
+
+            for line in &lines[1..]
+            {
+                result += &format!("{}{}\n", " ".repeat(column), line);
+            }
+
+            result
+        }
+        else
+        {
+            let description = wrap(&indent(description, 8), width).join("\n");
+
+            format!("  {}\n{}\n", name, description)
+        }
+    }
+
+    /// Render the help with every line wrapped to fit within `width` columns,
+    /// or 40 columns if `width` is narrower
+    pub fn render_wrapped(&self, width: usize) -> String
+    {
+        let width = width.max(MIN_HELP_WIDTH);
+        let mut result = indent(&wrap(&format!("Usage: {}", self.usage), width.saturating_sub(7)).join("\n"), 7);
+        result = format!("{}\n{}\n\n", result.trim_start(), wrap(&self.description, width).join("\n"));
+
+        let name_width = if self.global_alignment { self.max_option_name_width() } else { 31 };
+
+        if self.has_commands()
+        {
+            result += "Commands:\n";
+
+            // Commands are aligned with the descriptions of the options
+            for (name, description) in &self.commands
+            {
+                result += &HelpDisplay::render_item(name, description, name_width, width);
+            }
+
+            result += "\n";
+
+            if !self.entries.is_empty()
+            {
+                result += "Options:\n";
+            }
+        }
+
+        for entry in &self.entries
+        {
+            result += &HelpDisplay::render_item(&entry.names(), &entry.description, name_width, width);
+        }
+
+        result + "\n"
+    }
+
+    /// Check if any subcommands have been added
+    pub fn has_commands(&self) -> bool
+    {
+        !self.commands.is_empty()
+    }
+
+    /// Find the flags in `known_flags` which do not have a corresponding
+    /// OptionEntry
+    pub fn find_undocumented<'a>(&self, known_flags: &'a [&str]) -> Vec<&'a str>
+    {
+        known_flags.iter()
+            .filter(|flag| !self.entries.iter().any(|entry| entry.matches(flag)))
+            .copied()
+            .collect()
+    }
+
+    /// Find the names of the OptionEntries which do not correspond to any of
+    /// the flags in `known_flags`
+    pub fn find_phantom(&self, known_flags: &[&str]) -> Vec<String>
+    {
+        self.entries.iter()
+            .filter(|entry| !known_flags.iter().any(|flag| entry.matches(flag)))
+            .map(|entry| entry.name())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for HelpDisplay
+{
+    /// Format the help wrapped to the width of the terminal, or 80 columns if
+    /// it is not known
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.render_wrapped(terminal_width().unwrap_or(80)))
+    }
+}
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Parse arguments given without the program name
+    fn args(args: &[&str]) -> Arguments
+    {
+        Arguments::from_vec(std::iter::once("prog").chain(args.iter().copied()).map(String::from).collect())
+    }
+
+    /// Build a row of cells
+    fn row(cells: &[&str]) -> Vec<String>
+    {
+        cells.iter().map(|cell| String::from(*cell)).collect()
+    }
+
+    /// Look up environment variables from a fixed list rather than the process
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String>
+    {
+        move |var| vars.iter().find(|(name, _)| *name == var).map(|(_, val)| String::from(*val))
+    }
+
+    /// Run `f` with the given global color mode and without forcing colors in
+    /// CI, restoring the global settings afterwards
+    fn with_color_mode<T, F: FnOnce() -> T>(mode: ColorMode, f: F) -> T
+    {
+        let _guard = settings();
+
+        set_color_mode(mode);
+        FORCE_COLOR_IN_CI.store(false, Ordering::Relaxed);
+        f()
+    }
+
+    #[test]
+    fn separator_ends_options()
+    {
+        let args = args(&["--verbose", "--", "--output", "file"]);
+
+        assert!(args.check_arg("--verbose"));
+        assert!(!args.check_arg("--output"));
+        assert_eq!(args.args_after_separator(), ["--output", "file"]);
+        assert_eq!(args.naked_values, ["--output", "file"]);
+    }
+
+    #[test]
+    fn positionals_bind_by_name()
+    {
+        let bound = args(&["--force", "src", "dst"]).bind_positionals(&["source", "destination"]).unwrap();
+
+        assert_eq!(bound["source"], "src");
+        assert_eq!(bound["destination"], "dst");
+        assert!(args(&["src"]).bind_positionals(&["source", "destination"]).is_err());
+    }
+
+    #[test]
+    fn positional_after_a_flag_passes_the_usage_check()
+    {
+        let args = args(&["--verbose", "build"]);
+
+        assert_eq!(args.print_usage_if_no_positionals("tool <command>").naked_values, ["build"]);
+    }
+
+    #[test]
+    fn multi_line_cells_keep_columns_aligned()
+    {
+        let mut grid = GridDisplay::new(row(&["name", "notes"]));
+        grid.add_row(row(&["a", "first\nsecond"]));
+        grid.add_row(row(&["bb", "x"]));
+
+        assert_eq!(grid.render(), "name  notes   \na     first   \n      second  \nbb    x       \n");
+    }
+
+    #[test]
+    fn help_checks_find_undocumented_and_phantom_options()
+    {
+        let mut help = HelpDisplay::new("prog [options]", "Test program");
+        help.add_option(OptionEntry::new("v", "verbose", "", "Print more output"));
+        help.add_option(OptionEntry::new("", "legacy", "", "No longer parsed"));
+
+        assert_eq!(help.find_undocumented(&["-v", "--verbose", "--output"]), ["--output"]);
+        assert_eq!(help.find_phantom(&["-v", "--output"]), ["--legacy"]);
+    }
+
+    #[test]
+    fn verbosity_from_flags()
+    {
+        assert_eq!(args(&[]).verbosity(), Verbosity::Normal);
+        assert_eq!(args(&["-v"]).verbosity(), Verbosity::Verbose);
+        assert_eq!(args(&["-q"]).verbosity(), Verbosity::Quiet);
+        assert_eq!(args(&["-vv"]).verbosity(), Verbosity::Debug);
+    }
+
+    #[test]
+    fn numeric_columns_are_right_aligned()
+    {
+        let mut grid = GridDisplay::new(row(&["name", "count"]));
+        grid.add_row(vec![String::from("apple"), decorate_color(String::from("5"), AnsiColor::Red)]);
+        grid.add_row(row(&["kiwi", "120"]));
+        grid.set_auto_numeric_align(true);
+
+        assert_eq!(clear_decoration(grid.render()), "name   count  \napple      5  \nkiwi     120  \n");
+    }
+
+    #[test]
+    fn report_and_returns_default_after_reporting_once()
+    {
+        let mut error = CliError::new("Cannot read config", 1, ErrorLevel::Error);
+
+        assert_eq!(error.report_and(5), 5);
+        assert!(error.reported);
+        assert_eq!(error.report_and(7), 7);
+        assert!(error.reported);
+    }
+
+    #[test]
+    fn repeated_options_yield_ordered_pairs()
+    {
+        let args = args(&["--rename", "old", "new", "--rename", "a", "b"]);
+
+        assert_eq!(args.get_pairs("--rename"), [(String::from("old"), String::from("new")), (String::from("a"), String::from("b"))]);
+    }
+
+    #[test]
+    fn color_mode_none_strips_decorations()
+    {
+        let mut grid = GridDisplay::new(vec![decorate(String::from("name"), AnsiColor::Red, AnsiStyle::Bold)]);
+        grid.add_row(vec![decorate_color(String::from("value"), AnsiColor::Green)]);
+        grid.with_title("Title");
+        grid.set_title_color(AnsiColor::Yellow);
+        grid.set_color_mode(ColorMode::None);
+
+        let rendered = grid.render();
+
+        assert!(!rendered.contains('\x1B'));
+        assert_eq!(rendered, clear_decoration(rendered.clone()));
+        assert!(rendered.contains("name   \nvalue  \n"));
+    }
+
+    #[test]
+    fn require_exactly_one_of_a_group()
+    {
+        let group = ["--json", "--yaml"];
+
+        assert!(args(&[]).require_exactly_one(&group).is_err());
+        assert!(args(&["--json"]).require_exactly_one(&group).is_ok());
+        assert!(args(&["--json", "--yaml"]).require_exactly_one(&group).is_err());
+    }
+
+    #[test]
+    fn lookups_on_arguments_built_from_values()
+    {
+        let mut values = HashMap::new();
+        values.insert(String::from("--id"), vec![String::from("1"), String::from("2")]);
+
+        let args = Arguments::from_values(vec![String::from("--id"), String::from("--force")], values, vec![String::from("file")]);
+
+        assert!(args.check_arg("--force"));
+        assert_eq!(args.get_passed::<i32>("--id"), Some(2));
+        assert_eq!(args.occurrences.len(), 3);
+        assert_eq!(args.naked_values, ["file"]);
+    }
+
+    #[test]
+    fn commands_render_with_aligned_descriptions()
+    {
+        let mut help = HelpDisplay::new("prog <command>", "Test program");
+        help.add_command("build", "Build the project");
+        help.add_command("test-all", "Run every test");
+
+        let rendered = help.to_string();
+        let column = |description: &str| rendered.lines().find(|line| line.contains(description)).and_then(|line| line.find(description));
+
+        assert!(rendered.contains("Commands:\n"));
+        assert!(column("Build the project").is_some());
+        assert_eq!(column("Build the project"), column("Run every test"));
+    }
+
+    #[test]
+    fn wide_headers_size_columns_by_display_width()
+    {
+        let mut grid = GridDisplay::new(row(&["名前"]));
+        grid.add_row(row(&["a"]));
+        grid.add_row(row(&["bc"]));
+
+        assert_eq!(grid.render(), "名前  \na     \nbc    \n");
+    }
+
+    #[test]
+    fn extend_merges_grids()
+    {
+        let mut grid = GridDisplay::new(row(&["name"]));
+        grid.add_row(row(&["a"]));
+
+        let mut other = GridDisplay::new(row(&["name"]));
+        other.add_row(row(&["b"]));
+        other.add_row(row(&["c"]));
+
+        assert!(grid.extend(other).is_ok());
+        assert_eq!(grid.render(), "name  \na     \nb     \nc     \n");
+
+        assert!(grid.extend(GridDisplay::new(row(&["other"]))).is_err());
+    }
+
+    #[test]
+    fn hidden_columns_are_not_rendered()
+    {
+        let mut grid = GridDisplay::new(row(&["a", "b", "c"]));
+        grid.add_row(row(&["1", "2", "3"]));
+        grid.set_visible_columns(&[0, 2]);
+
+        assert_eq!(grid.render(), "a  c  \n1  3  \n");
+
+        grid.show_all_columns();
+
+        assert_eq!(grid.render(), "a  b  c  \n1  2  3  \n");
+    }
+
+    #[test]
+    fn color_reset_is_only_written_to_terminals()
+    {
+        let mut terminal = vec![];
+        let mut file = vec![];
+
+        ColorGuard::reset(&mut terminal, true).unwrap();
+        ColorGuard::reset(&mut file, false).unwrap();
+
+        assert_eq!(terminal, b"\x1B[0m");
+        assert!(file.is_empty());
+    }
+
+    #[test]
+    fn format_number_groups_digits()
+    {
+        assert_eq!(format_number(1234567.0, &NumberFormat::new()), "1,234,567");
+        assert_eq!(format_number(123.0, &NumberFormat::new()), "123");
+        assert_eq!(format_number(-1234.5, &NumberFormat::new()), "-1,234.5");
+    }
+
+    #[test]
+    fn format_number_with_custom_separator()
+    {
+        assert_eq!(format_number(1000000.0, &NumberFormat::new().with_separator(Some('_'))), "1_000_000");
+        assert_eq!(format_number(1000000.0, &NumberFormat::new().with_separator(None)), "1000000");
+    }
+
+    #[test]
+    fn format_number_rounds_to_precision()
+    {
+        assert_eq!(format_number(1234.567, &NumberFormat::new().with_precision(2)), "1,234.57");
+        assert_eq!(format_number(9999.996, &NumberFormat::new().with_precision(2)), "10,000.00");
+        assert_eq!(format_number(12.4, &NumberFormat::new().with_precision(0)), "12");
+    }
+
+    #[test]
+    fn lone_dash_is_a_positional()
+    {
+        let args = args(&["-"]);
+
+        assert!(args.args.is_empty());
+        assert_eq!(args.naked_values, ["-"]);
+    }
+
+    #[test]
+    fn color_environment_variables_follow_their_precedence()
+    {
+        with_color_mode(ColorMode::Auto, ||
+        {
+            assert!(color_supported_with(env(&[]), true));
+            assert!(!color_supported_with(env(&[]), false));
+
+            assert!(color_supported_with(env(&[("CLICOLOR_FORCE", "1")]), false));
+            assert!(color_supported_with(env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]), false));
+            assert!(color_supported_with(env(&[("CLICOLOR_FORCE", "1"), ("CLICOLOR", "0")]), false));
+            assert!(!color_supported_with(env(&[("CLICOLOR_FORCE", "0")]), false));
+
+            assert!(!color_supported_with(env(&[("NO_COLOR", "1")]), true));
+            assert!(color_supported_with(env(&[("NO_COLOR", "")]), true));
+            assert!(!color_supported_with(env(&[("NO_COLOR", "1"), ("CLICOLOR", "1")]), true));
+
+            assert!(!color_supported_with(env(&[("CLICOLOR", "0")]), true));
+            assert!(color_supported_with(env(&[("CLICOLOR", "1")]), true));
+            assert!(!color_supported_with(env(&[("CLICOLOR", "1")]), false));
+        });
+    }
+
+    #[test]
+    fn column_formatter_changes_rendered_values()
+    {
+        let mut grid = GridDisplay::new(row(&["name", "code"]));
+        grid.add_row(row(&["alpha", "ab"]));
+        grid.set_column_formatter(1, |val| val.to_uppercase());
+
+        assert_eq!(grid.render(), "name   code  \nalpha  AB    \n");
+        assert_eq!(grid.render_csv(), "name,code\nalpha,ab\n");
+    }
+
+    #[test]
+    fn choices_match_values_ignoring_case()
+    {
+        let args = args(&["--format", "JsOn"]);
+
+        assert_eq!(args.get_choice_ci("--format", &["json", "yaml"]).unwrap(), "json");
+        assert!(args.get_choice("--format", &["json", "yaml"]).is_err());
+        assert!(args.get_choice_ci("--format", &["yaml", "toml"]).is_err());
+    }
+
+    #[test]
+    fn short_form_excludes_package_prefix()
+    {
+        let error = CliError::new("File not found", 2, ErrorLevel::Error);
+
+        assert_eq!(error.short(), "[2] File not found");
+        assert!(!error.short().contains(env!("CARGO_PKG_NAME")));
+    }
+
+    #[test]
+    fn parse_error_points_at_the_invalid_occurrence()
+    {
+        let error = args(&["--id", "1", "--id", "2", "--id", "x"]).get_passed_all::<i32>("--id").unwrap_err();
+
+        assert_eq!(error.error, "Cannot parse value 'x' at index 2 of '--id'");
+        assert_eq!(args(&["--id", "1", "--id", "2"]).get_passed_all::<i32>("--id").unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn repeated_options_collect_every_value()
+    {
+        let args = args(&["--tag", "a", "--tag", "b"]);
+
+        assert_eq!(args.get_multi_passed::<String>("--tag"), ["a", "b"]);
+        assert_eq!(args.get_passed::<String>("--tag"), Some(String::from("b")));
+    }
+
+    #[test]
+    fn box_surrounds_each_line()
+    {
+        assert_eq!(boxed_with("hi\nthere", None, None, true), "┌───────┐\n│ hi    │\n│ there │\n└───────┘\n");
+    }
+
+    #[test]
+    fn arguments_with_only_the_program_name_are_empty()
+    {
+        assert!(args(&[]).is_empty());
+        assert!(!args(&["file"]).is_empty());
+        assert!(!args(&["-v"]).is_empty());
+    }
+
+    #[test]
+    fn sorted_entries_are_alphabetical()
+    {
+        let mut help = HelpDisplay::new("prog [options]", "Test program");
+        help.add_option(OptionEntry::from_long_only("verbose", "Print more output"));
+        help.add_option(OptionEntry::from_short_only("b", "Run in the background"));
+        help.add_option(OptionEntry::from_long_only("Output", "Write to a file"));
+        help.sort_entries();
+
+        let names: Vec<String> = help.entries.iter().map(|entry| entry.name()).collect();
+
+        assert_eq!(names, ["-b", "--Output", "--verbose"]);
+    }
+
+    #[test]
+    fn streaming_rows_are_written_immediately()
+    {
+        let mut grid = StreamingGridDisplay::with_headers(vec![], row(&["name", "id"])).unwrap();
+
+        assert_eq!(grid.writer, b"name  id  \n");
+
+        grid.add_row(row(&["a", "1"])).unwrap();
+
+        assert_eq!(grid.writer, b"name  id  \na     1   \n");
+
+        grid.add_row(row(&["abcdefg", "22"])).unwrap();
+
+        assert_eq!(String::from_utf8(grid.into_inner()).unwrap(), "name  id  \na     1   \nabcd  22  \n");
+    }
+
+    #[test]
+    fn tab_separated_output_escapes_embedded_tabs()
+    {
+        let mut grid = GridDisplay::new(row(&["key", "value"]));
+        grid.add_row(row(&["a\tb", "c\\d"]));
+
+        assert_eq!(grid.to_tsv(), "key\tvalue\na\\tb\tc\\\\d\n");
+        assert_eq!(grid.render_tsv(), grid.to_tsv());
+        assert_eq!(grid.render_delimited('\t', '"'), "key\tvalue\n\"a\tb\"\tc\\d\n");
+    }
+
+    #[test]
+    fn custom_prefixes_recognize_options()
+    {
+        let raw = ["prog", "/verbose", "+xy", "-file"].iter().map(|arg| String::from(*arg)).collect();
+        let args = Arguments::from_vec_with_prefixes(raw, &["/"], &["+"]);
+
+        assert!(args.check_arg("/verbose"));
+        assert!(args.check_arg("+x"));
+        assert!(args.check_arg("+y"));
+        assert_eq!(args.get_single("+y"), Some(String::from("-file")));
+    }
+
+    #[test]
+    fn display_counted_returns_the_number_of_rows()
+    {
+        let mut grid = GridDisplay::new(row(&["name"]));
+        grid.add_row(row(&["a"]));
+        grid.add_row(row(&["b"]));
+        grid.add_row(row(&["c"]));
+
+        assert_eq!(grid.display_counted(), 3);
+    }
+
+    #[test]
+    fn columns_resolve_by_name_and_negative_index()
+    {
+        let mut grid = GridDisplay::new(vec![String::from("name"), decorate_color(String::from("size"), AnsiColor::Red)]);
+        grid.add_row(row(&["a", "1"]));
+
+        assert_eq!(grid.column_index("size"), Some(1));
+        assert_eq!(grid.column_index("missing"), None);
+        assert_eq!(grid.resolve_column(-1).unwrap(), 1);
+        assert_eq!(grid.resolve_column(-2).unwrap(), 0);
+        assert!(grid.resolve_column(-3).is_err());
+        assert!(grid.resolve_column(2).is_err());
+
+        let last = grid.resolve_column(-1).unwrap();
+        grid.align_right(last);
+
+        assert_eq!(grid.alignments.get(&1), Some(&ColumnAlignment::Right));
+    }
+
+    #[test]
+    fn ansi_parser_tokenizes_colors_text_and_cursor_movement()
+    {
+        let tokens: Vec<AnsiToken> = AnsiParser::new("\x1B[1;31mred\x1B[0m plain\x1B[2Aend").collect();
+
+        assert_eq!(tokens, [
+            AnsiToken::Sgr(vec![1, 31]),
+            AnsiToken::Text("red"),
+            AnsiToken::Sgr(vec![0]),
+            AnsiToken::Text(" plain"),
+            AnsiToken::Other("\x1B[2A"),
+            AnsiToken::Text("end")
+        ]);
+        assert_eq!(clear_decoration(String::from("\x1B[1;31mred\x1B[0m plain\x1B[2Aend")), "red plainend");
+    }
+
+    #[test]
+    fn try_get_passed_keeps_the_parse_error()
+    {
+        let args = args(&["--count", "abc", "--size", "3"]);
+
+        assert!(args.try_get_passed::<i32>("--count").is_err());
+        assert_eq!(args.try_get_passed::<i32>("--size"), Ok(Some(3)));
+        assert_eq!(args.try_get_passed::<i32>("--missing"), Ok(None));
+    }
+
+    #[test]
+    fn unicode_border_header_separator_meets_columns()
+    {
+        let mut grid = GridDisplay::new(row(&["a", "bb", "ccc"]));
+        grid.add_row(row(&["1", "2", "3"]));
+        grid.set_border_style(BorderStyle::Unicode);
+
+        let rendered = grid.render();
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines[0], "┌───┬────┬─────┐");
+        assert_eq!(lines[1], "│ a │ bb │ ccc │");
+        assert_eq!(lines[2], "├───┼────┼─────┤");
+        assert_eq!(lines[4], "└───┴────┴─────┘");
+    }
+
+    #[test]
+    fn report_includes_causes_and_context()
+    {
+        let error = CliError::new("Cannot load config", 1, ErrorLevel::Error)
+            .with_source(CliError::new("File not found", 2, ErrorLevel::Error))
+            .with_context("path", "/etc/app.toml");
+
+        let report = with_color_mode(ColorMode::None, || error.report());
+
+        assert!(report.contains("Cannot load config"));
+        assert!(report.contains("Caused by:\n  0: File not found\n"));
+        assert!(report.contains("Context:\n  path: /etc/app.toml\n"));
+    }
+
+    #[test]
+    fn positionals_are_checked_against_their_limits()
+    {
+        let args = args(&["a", "b", "c"]);
+
+        assert!(args.positionals_checked(4, None).is_err());
+        assert!(args.positionals_checked(1, Some(2)).is_err());
+        assert_eq!(args.positionals_checked(1, Some(3)).unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn should_colorize_follows_color_mode_and_environment()
+    {
+        with_color_mode(ColorMode::Auto, ||
+        {
+            assert!(should_colorize_with(env(&[]), true));
+            assert!(!should_colorize_with(env(&[]), false));
+            assert!(!should_colorize_with(env(&[("NO_COLOR", "1")]), true));
+            assert!(should_colorize_with(env(&[("CLICOLOR_FORCE", "1")]), false));
+            assert!(!should_colorize_with(env(&[("CLICOLOR", "0")]), true));
+            assert!(!should_colorize_with(env(&[("CI", "true")]), true));
+
+            FORCE_COLOR_IN_CI.store(true, Ordering::Relaxed);
+            assert!(should_colorize_with(env(&[("CI", "true")]), true));
+        });
+
+        with_color_mode(ColorMode::Full, ||
+        {
+            assert!(should_colorize_with(env(&[("NO_COLOR", "1")]), false));
+        });
+
+        with_color_mode(ColorMode::None, ||
+        {
+            assert!(!should_colorize_with(env(&[("CLICOLOR_FORCE", "1")]), true));
+        });
+    }
+
+    #[test]
+    fn right_align_selected_columns()
+    {
+        let mut grid = GridDisplay::new(row(&["name", "size", "kind"]));
+        grid.add_row(row(&["a", "1", "file"]));
+        grid.add_row(row(&["bb", "100", "dir"]));
+        grid.set_right_align(&[1]);
+
+        assert_eq!(grid.render(), "name  size  kind  \na        1  file  \nbb     100  dir   \n");
+    }
+
+    #[test]
+    fn retry_succeeds_on_a_later_attempt()
+    {
+        let mut calls = 0;
+
+        let result = CliError::retry(3, std::time::Duration::from_millis(0), ||
+        {
+            calls += 1;
+            if calls < 3 { CliError::error("Not ready", 1) } else { Ok(calls) }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+
+        let error = CliError::retry(2, std::time::Duration::from_millis(0), || CliError::error::<()>("Not ready", 1)).unwrap_err();
+
+        assert_eq!(error.context(), [(String::from("attempts"), String::from("2"))]);
+    }
+
+    #[test]
+    fn positions_of_a_repeated_flag()
+    {
+        let args = args(&["-v", "file", "--out", "x", "-vq"]);
+
+        assert_eq!(args.arg_positions("-v"), [1, 5]);
+        assert_eq!(args.arg_positions("--out"), [3]);
+        assert_eq!(args.arg_positions("-q"), [5]);
+        assert!(args.arg_positions("--missing").is_empty());
+    }
+
+    #[test]
+    fn borrowed_decorations_are_not_copied_when_disabled()
+    {
+        let text = "plain text";
+
+        with_color_mode(ColorMode::None, ||
+        {
+            let colored = decorate_color_cow(text, AnsiColor::Red);
+            let styled = decorate_cow(text, AnsiColor::Red, AnsiStyle::Bold);
+
+            assert!(matches!(colored, std::borrow::Cow::Borrowed(_)));
+            assert!(std::ptr::eq(colored.as_ptr(), text.as_ptr()));
+            assert!(std::ptr::eq(styled.as_ptr(), text.as_ptr()));
+            assert!(!colored.contains('\x1B'));
+        });
+
+        with_color_mode(ColorMode::Full, ||
+        {
+            assert_eq!(decorate_color_cow(text, AnsiColor::Red), decorate_color(String::from(text), AnsiColor::Red));
+        });
+    }
+
+    #[test]
+    fn header_repeats_every_two_rows()
+    {
+        let mut grid = GridDisplay::new(row(&["id"]));
+        for i in 1..=5
+        {
+            grid.add_row(vec![i.to_string()]);
+        }
+        grid.set_repeat_header(2);
+
+        assert_eq!(grid.render(), "id  \n1   \n2   \nid  \n3   \n4   \nid  \n5   \n");
+    }
+
+    #[test]
+    fn values_are_case_converted_at_lookup()
+    {
+        let args = args(&["--mode", "Fast"]);
+
+        assert_eq!(args.get_single_lower("--mode"), Some(String::from("fast")));
+        assert_eq!(args.get_single_upper("--mode"), Some(String::from("FAST")));
+        assert_eq!(args.get_single("--mode"), Some(String::from("Fast")));
+    }
+
+    #[test]
+    fn help_wraps_to_the_given_width()
+    {
+        let mut help = HelpDisplay::new("prog [options] <input> <output>", "Convert the input file to the output format, keeping its metadata");
+        help.add_option(OptionEntry::new("v", "verbose", "", "Print more output than usual, which is useful when debugging"));
+        help.add_option(OptionEntry::new("f", "format", "<name>", "Output format"));
+
+        let rendered = help.render_wrapped(40);
+
+        assert!(rendered.lines().all(|line| display_width(line) <= 40), "{}", rendered);
+        assert!(rendered.contains("\n  -v, --verbose\n"));
+        assert!(rendered.contains("\n  -f, --format     <name>\n"));
+    }
+
+    #[test]
+    fn wrap_breaks_between_words()
+    {
+        assert_eq!(wrap("one two three", 7), ["one two", "three"]);
+        assert_eq!(wrap("abcdef", 4), ["abcd", "ef"]);
+    }
+
+    #[test]
+    fn bottom_aligned_cells_sit_on_the_last_line()
+    {
+        let mut grid = GridDisplay::empty();
+        grid.add_row(row(&["a\nb\nc", "x"]));
+        grid.set_vertical_align(VAlign::Bottom);
+
+        assert_eq!(grid.render(), "a     \nb     \nc  x  \n");
+    }
+
+    #[test]
+    fn level_prefixes_with_and_without_color()
+    {
+        with_color_mode(ColorMode::Full, ||
+        {
+            assert_eq!(level_prefix(ErrorLevel::Error), "\x1B[1m\x1B[31merror\x1B[0m: ");
+            assert_eq!(level_prefix(ErrorLevel::Warning), "\x1B[1m\x1B[33mwarning\x1B[0m: ");
+            eprintln_error!("shown with {}", "color");
+        });
+
+        with_color_mode(ColorMode::None, ||
+        {
+            assert_eq!(level_prefix(ErrorLevel::Error), "error: ");
+            assert_eq!(level_prefix(ErrorLevel::Warning), "warning: ");
+            eprintln_warn!("shown without {}", "color");
+        });
+    }
+
+    #[test]
+    fn status_lines_have_a_colored_icon_per_level()
     {
-        AnsiStyle::Bold => "\x1B[1m",
-        AnsiStyle::Strikethrough => "\x1B[9m",
-        AnsiStyle::Underline => "\x1B[4m"
-    });
+        let status = |level: StatusLevel, colorize: bool|
+        {
+            let mut writer = vec![];
+            write_status(&mut writer, level, "done", colorize).unwrap();
+            String::from_utf8(writer).unwrap()
+        };
 
-    start + &clear_decoration(s) + &String::from("\x1B[0m")
-}
+        let mut unicode = Settings::current();
+        unicode.verbosity = Verbosity::Normal;
+        unicode.unicode = true;
 
-/// Decorate with a color and style
-pub fn decorate(s: String, color: AnsiColor, style: AnsiStyle) -> String
-{
-    let start = String::from(match style
+        with_settings(unicode, ||
         {
-            AnsiStyle::Bold => "\x1B[1m",
-            AnsiStyle::Strikethrough => "\x1B[9m",
-            AnsiStyle::Underline => "\x1B[4m"
+            assert_eq!(status(StatusLevel::Success, true), "\x1B[1m\x1B[32m✓\x1B[0m done\n");
+            assert_eq!(status(StatusLevel::Info, true), "\x1B[1m\x1B[34mℹ\x1B[0m done\n");
+            assert_eq!(status(StatusLevel::Warning, true), "\x1B[1m\x1B[33m⚠\x1B[0m done\n");
+            assert_eq!(status(StatusLevel::Error, true), "\x1B[1m\x1B[31m✗\x1B[0m done\n");
+            assert_eq!(status(StatusLevel::Success, false), "✓ done\n");
+
+            UNICODE_SUPPORTED.store(false, Ordering::Relaxed);
+
+            assert_eq!(status(StatusLevel::Error, false), "x done\n");
         });
-    
-    start + &decorate_color(s, color)
-}
+    }
 
-/// Decorate with a color and multiple styles
-pub fn decorate_multiple(s: String, color: AnsiColor, styles: Vec<AnsiStyle>) -> String
-{
-    let mut result = decorate_color(s, color);
+    #[test]
+    fn explicit_empty_values_differ_from_missing_values()
+    {
+        assert_eq!(args(&["--key="]).get_single("--key"), Some(String::new()));
+        assert_eq!(args(&["--key"]).get_single("--key"), None);
+        assert_eq!(args(&["--key", "v"]).get_single("--key"), Some(String::from("v")));
+    }
 
-    for style in &styles
+    #[test]
+    fn custom_separator_keeps_columns_aligned()
     {
-        let start = String::from(match style
-            {
-                AnsiStyle::Bold => "\x1B[1m",
-                AnsiStyle::Strikethrough => "\x1B[9m",
-                AnsiStyle::Underline => "\x1B[4m"
-            });
+        let mut grid = GridDisplay::new(row(&["name", "size"]));
+        grid.add_row(row(&["a", "100"]));
+        grid.add_row(row(&["longer", "1"]));
+        grid.set_separator(" | ");
 
-        result = start + &result;
+        assert_eq!(grid.render(), "name   | size\na      | 100 \nlonger | 1   \n");
     }
 
-    result
-}
+    #[test]
+    fn list_options_mix_repeats_and_separators()
+    {
+        let args = args(&["--tag", "a,b", "--tag", "c", "--tag", " d , ,e"]);
 
+        assert_eq!(args.get_multi("--tag", ','), ["a", "b", "c", "d", "e"]);
+        assert!(args.get_multi("--missing", ',').is_empty());
+    }
 
-/// An object to allow data to be displayed in the console as a grid
-pub struct GridDisplay
-{
-    /// Optional Headers for each column
-    headers: Option<Vec<String>>,
-    /// Rows to be displayed
-    rows: Vec<Vec<String>>
-}
+    #[test]
+    fn settings_revert_after_their_scope()
+    {
+        let _outer = settings();
+        let before = Settings::current();
 
+        let mut changed = before.clone();
+        changed.verbosity = Verbosity::Debug;
+        changed.default_error_code = 42;
 
-impl GridDisplay
-{
-    /// Generate an empty GridDisplay object
-    pub fn empty() -> GridDisplay
-    {
-        GridDisplay
+        let nested = with_settings(changed, ||
         {
-            headers: None,
-            rows: vec![]
+            set_color_mode(ColorMode::None);
+
+            let mut quiet = Settings::current();
+            quiet.verbosity = Verbosity::Quiet;
+
+            with_settings(quiet, || (verbosity(), CliError::default_code(), color_mode()))
+        });
+
+        assert_eq!(nested, (Verbosity::Quiet, 42, ColorMode::None));
+        assert_eq!(Settings::current(), before);
+
+        {
+            let _guard = settings();
+            set_verbosity(Verbosity::Verbose);
         }
+
+        assert_eq!(Settings::current(), before);
     }
 
-    /// Generate a GridDisplay object with the given header
-    pub fn new(headers: Vec<String>) -> GridDisplay
+    #[test]
+    fn custom_error_code_propagates()
     {
-        GridDisplay
+        let invalid = args(&["--port", "abc"]);
+
+        assert_eq!(invalid.get_passed_checked_code::<u16>("--port", 64).unwrap_err().error_code, 64);
+        assert_eq!(invalid.get_passed_checked_code::<u16>("--missing", 65).unwrap_err().error_code, 65);
+        assert_eq!(args(&["--port", "80"]).get_passed_checked_code::<u16>("--port", 64).unwrap(), 80);
+    }
+
+    #[test]
+    fn decorated_grid_cells_align_like_plain_cells()
+    {
+        let red = |s: &str| decorate(String::from(s), AnsiColor::Red, AnsiStyle::Bold);
+
+        for border in [BorderStyle::None, BorderStyle::Unicode]
         {
-            headers: Some(headers),
-            rows: vec![]
+            let mut plain = GridDisplay::new(row(&["name", "size"]));
+            plain.add_row(row(&["alpha", "1"]));
+            plain.add_row(row(&["b", "200"]));
+            plain.set_border_style(border);
+            plain.align_right(1);
+
+            let mut decorated = GridDisplay::new(vec![red("name"), String::from("size")]);
+            decorated.add_row(vec![String::from("alpha"), red("1")]);
+            decorated.add_row(vec![red("b"), String::from("200")]);
+            decorated.set_border_style(border);
+            decorated.align_right(1);
+            decorated.set_color_mode(ColorMode::Full);
+
+            let rendered = decorated.render();
+
+            assert!(rendered.contains('\x1B'));
+            assert_eq!(clear_decoration(rendered), plain.render());
         }
     }
 
-    /// Set the header for a GridDispaly object
-    pub fn set_header(&mut self, headers: Vec<String>)
+    #[test]
+    fn decorated_help_names_align_like_plain_names()
     {
-        self.headers = Some(headers);
+        let help = |long: &str, command: &str|
+        {
+            let mut help = HelpDisplay::new("prog", "Test program");
+            help.add_command(command, "Run a command");
+            help.add_option(OptionEntry::new("v", long, "", "Print more output"));
+            help.add_option(OptionEntry::new("o", "output", "<file>", "Write to a file"));
+            help
+        };
+
+        let plain = help("verbose", "run");
+        let decorated = help(&decorate_color(String::from("verbose"), AnsiColor::Green), &decorate_color(String::from("run"), AnsiColor::Green));
+
+        assert_eq!(clear_decoration(decorated.render_wrapped(80)), plain.render_wrapped(80));
+        assert_eq!(clear_decoration(decorated.render_wrapped(40)), plain.render_wrapped(40));
+        assert_eq!(decorated.max_option_name_width(), plain.max_option_name_width());
     }
 
-    /// Add a row to the GridDisplay
-    pub fn add_row(&mut self, row: Vec<String>)
+    #[test]
+    fn bordered_short_rows_keep_the_right_edge()
     {
-        self.rows.push(row);
+        let mut grid = GridDisplay::new(row(&["a"]));
+        grid.add_row(row(&["1", "2", "3"]));
+        grid.add_row(row(&["4"]));
+        grid.set_border_style(BorderStyle::Ascii);
+
+        assert_eq!(grid.render(), "+---+---+---+\n| a |   |   |\n+---+---+---+\n| 1 | 2 | 3 |\n| 4 |   |   |\n+---+---+---+\n");
     }
 
-    /// Pad a string on the right with spaces to match a given length
-    fn pad(s: String, width: usize) -> String
+    #[test]
+    fn wide_spanning_rows_widen_the_grid()
     {
-        let mut result = s.clone();
+        let mut grid = GridDisplay::new(row(&["a", "b"]));
+        grid.add_row(row(&["1", "2"]));
+        grid.add_spanning_row(String::from("a long spanning value"), 2);
+        grid.set_border_style(BorderStyle::Ascii);
 
-        for _ in 0..(width - clear_decoration(s).len())
-        {
-            result += " ";
-        }
+        let rendered = grid.render();
 
-        result
+        assert!(rendered.lines().all(|line| display_width(line) == 25), "{}", rendered);
+        assert!(rendered.contains("| a long spanning value |\n"));
+        assert_eq!(grid.total_rendered_width(), 25);
     }
 
-    /// Render the GridDisplay to a string
-    pub fn render(&self) -> String
+    #[test]
+    fn grids_and_boxes_follow_the_global_color_mode()
     {
-        let mut result = String::from("");
+        let mut grid = GridDisplay::new(row(&["name"]));
+        grid.add_row(row(&["a"]));
+        grid.with_title("Title");
+        grid.set_title_color(AnsiColor::Yellow);
+        grid.set_column_color(0, AnsiColor::Blue);
+        grid.set_border_style(BorderStyle::Ascii);
+        grid.set_border_color(AnsiColor::Cyan);
 
-        let mut max_column_sizes: Vec<usize> = vec![];
+        with_color_mode(ColorMode::None, ||
+        {
+            assert!(!grid.render().contains('\x1B'));
+            assert!(!boxed("text", Some(AnsiColor::Red)).contains('\x1B'));
+        });
 
-        match &self.headers
+        with_color_mode(ColorMode::Full, ||
         {
-            Some(headers) =>
-            {
-                for header in headers
-                {
-                    max_column_sizes.push(clear_decoration(header.clone()).len());
-                }
-            },
-            None => 
-            {
-                if self.rows.len() > 0
-                {
-                    for val in &self.rows[0]
-                    {
-                        max_column_sizes.push(clear_decoration(val.clone()).len());
-                    }
-                }
-            }
-        }
+            assert!(grid.render().contains('\x1B'));
+            assert!(boxed("text", Some(AnsiColor::Red)).contains('\x1B'));
+        });
+    }
 
-        let mut i: usize;
+    #[test]
+    fn narrow_help_is_clamped_to_a_minimum_width()
+    {
+        let mut help = HelpDisplay::new("prog", "Test program");
+        help.add_option(OptionEntry::new("v", "verbose", "", "Print more output"));
 
-        for row in &self.rows
-        {
-            i = 0;
+        assert_eq!(help.render_wrapped(0), help.render_wrapped(40));
+        assert!(help.render_wrapped(0).contains("\n  -v, --verbose\n        Print more output\n"));
+    }
 
-            for val in row
-            {
-                if i >= max_column_sizes.len()
-                {
-                    max_column_sizes.push(clear_decoration(val.clone()).len());
-                }
-                else
-                {
-                    if clear_decoration(val.clone()).len() > max_column_sizes[i]
-                    {
-                        max_column_sizes[i] = clear_decoration(val.clone()).len();
-                    }
-                }
-                i += 1;
-            }
-        }
+    #[test]
+    fn wrap_keeps_leading_indentation()
+    {
+        assert_eq!(wrap("  one two three", 9), ["  one two", "  three"]);
+        assert_eq!(wrap("abcdef", 4), ["abcd", "ef"]);
+    }
 
-        match &self.headers
+    #[test]
+    fn init_keeps_settings_which_are_not_given()
+    {
+        with_settings(Settings::current(), ||
         {
-            Some(headers) =>
-            {
-                i = 0;
-                for header in headers
-                {
-                    result += &GridDisplay::pad(header.clone(), max_column_sizes[i] + 2);
-                    i += 1;
-                }
+            let log_file = Path::new("errors.log");
 
-                result += "\n";
-            },
-            None => {}
-        }
+            set_color_mode(ColorMode::None);
+            init_with_options(CliOptions { force_color_in_ci: Some(true), unicode: Some(false), log_file: Some(log_file), ..CliOptions::default() });
+            init();
 
-        for row in &self.rows
-        {
-            i = 0;
-            for val in row
-            {
-                result += &GridDisplay::pad(val.clone(), max_column_sizes[i] + 2);
-                i += 1;
-            } 
-            result += "\n";
-        }
+            assert_eq!(color_mode(), ColorMode::None);
+            assert!(!unicode_supported());
+            assert!(Settings::current().force_color_in_ci);
+            assert_eq!(Settings::current().log_file.as_deref(), Some(log_file));
 
-        result
+            init_with_options(CliOptions { force_color: Some(true), force_color_in_ci: Some(false), ..CliOptions::default() });
+
+            assert_eq!(color_mode(), ColorMode::Full);
+            assert!(!Settings::current().force_color_in_ci);
+        });
     }
 
-    /// Display the GridDisplay object
-    pub fn display(&self)
+    #[test]
+    fn json_errors_round_trip()
     {
-        print!("{}", self.render());
+        let error = CliError::new("Bad \"value\"\n", 3, ErrorLevel::Warning);
+        let parsed = CliError::from_json(&error.to_json()).unwrap();
+
+        assert_eq!(parsed.error, error.error);
+        assert_eq!(parsed.error_code, 3);
+        assert_eq!(parsed.error_level, ErrorLevel::Warning);
     }
-}
 
-/// Help Option Entry
-#[derive(Debug, Clone)]
-pub struct OptionEntry
-{
-    /// Short name
-    short: String,
-    /// Long name
-    long: String,
-    /// Extra Info
-    extra: String,
-    /// Description
-    description: String
-}
+    #[test]
+    fn json_surrogate_pairs_are_decoded()
+    {
+        let parsed = CliError::from_json(r#"{"level":"error","code":1,"message":"smile \uD83D\uDE00","reported":false}"#).unwrap();
 
-impl OptionEntry
-{
-    /// Generate a new OptionEntry object
-    pub fn new(short: &str, long: &str, extra: &str, description: &str) -> Self
+        assert_eq!(parsed.error, "smile 😀");
+        assert!(CliError::from_json(r#"{"level":"error","code":1,"message":"\uD83D","reported":false}"#).is_err());
+        assert!(CliError::from_json(r#"{"level":"error","code":1,"message":"\uDE00","reported":false}"#).is_err());
+    }
+
+    #[test]
+    fn json_values_keep_their_type()
     {
-        Self
-        {
-            short: String::from(short),
-            long: String::from(long),
-            extra: String::from(extra),
-            description: String::from(description)
-        }
+        assert!(CliError::from_json(r#"{"level":"error","code":"42","message":"x","reported":false}"#).is_err());
+        assert!(CliError::from_json(r#"{"level":"error","code":42,"message":7,"reported":false}"#).is_err());
+        assert!(!CliError::from_json(r#"{"level":"error","code":42,"message":"x","reported":"true"}"#).unwrap().reported);
     }
-}
 
-impl std::fmt::Display for OptionEntry
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    #[test]
+    fn quiet_verbosity_hides_all_but_error_lines()
     {
-        let short_part = 
-        if self.short != ""
-        {
-            if self.long != ""
-            {
-                format!("-{},", self.short)
-            }
-            else
-            {
-                format!("-{}", self.short)
-            }
-        }
-        else
+        let status = |level: StatusLevel|
         {
-            String::new()
+            let mut writer = vec![];
+            write_status(&mut writer, level, "done", false).unwrap();
+            String::from_utf8(writer).unwrap()
         };
 
-        let long_part = 
-        if self.long != ""
+        let mut quiet = Settings::current();
+        quiet.verbosity = Verbosity::Quiet;
+        quiet.unicode = false;
+
+        with_settings(quiet, ||
         {
-            format!("--{:10} {}", self.long, self.extra)
-        }
-        else
+            assert_eq!(status(StatusLevel::Info), "");
+            assert_eq!(status(StatusLevel::Success), "");
+            assert_eq!(status(StatusLevel::Warning), "");
+            assert_eq!(status(StatusLevel::Error), "x done\n");
+        });
+    }
+
+    #[test]
+    fn zero_error_code_survives_a_round_trip()
+    {
+        let mut changed = Settings::current();
+        changed.default_error_code = 42;
+
+        with_settings(changed, ||
         {
-            format!("            {}", self.extra)
-        };
+            let error = CliError::new("Nothing went wrong", 0, ErrorLevel::Error);
 
-        write!(f, "  {:4}{:27} {}", short_part, long_part, self.description)
+            assert_eq!(error.error_code, 0);
+            assert_eq!(CliError::from_kv_string(&error.to_kv_string()).unwrap().error_code, 0);
+            assert_eq!(CliError::from_json(&error.to_json()).unwrap().error_code, 0);
+            assert_eq!(CliError::error_default::<()>("Failed").unwrap_err().error_code, 42);
+        });
     }
-}
-
-/// Help Display
-#[derive(Debug, Clone)]
-pub struct HelpDisplay
-{
-    /// Usage
-    usage: String,
-    /// Description
-    description: String,
-    /// Entries
-    entries: Vec<OptionEntry>
-}
 
-impl HelpDisplay
-{
-    /// Generate a new HelpDisplay object from the usage string and the description
-    /// string
-    pub fn new(usage: &str, description: &str) -> Self
+    #[test]
+    fn pairs_need_space_separated_values()
     {
-        Self
-        {
-            usage: String::from(usage),
-            description: String::from(description),
-            entries: vec![]
-        }
+        assert!(args(&["--rename=a", "b"]).get_pairs("--rename").is_empty());
+        assert_eq!(args(&["--rename", "a", "b"]).get_pairs("--rename"), [(String::from("a"), String::from("b"))]);
     }
 
-    /// Add another command line option
-    pub fn add_option(&mut self, entry: OptionEntry)
+    #[test]
+    fn spanning_rows_render_without_visible_columns()
     {
-        self.entries.push(entry);
+        let mut grid = GridDisplay::new(row(&["a", "b"]));
+        grid.add_row(row(&["1", "2"]));
+        grid.add_spanning_row(String::from("total"), 2);
+        grid.set_visible_columns(&[]);
+
+        assert_eq!(grid.render(), "  \n  \n  \n");
     }
-}
 
-impl std::fmt::Display for HelpDisplay
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    #[test]
+    fn dropping_a_color_guard_resets_its_writer()
     {
-        write!(f, "Usage: {}\n{}\n\n", self.usage, self.description)?;
+        /// A writer whose output can still be read after it is dropped
+        #[derive(Clone, Default)]
+        struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
 
-        for entry in &self.entries
+        impl std::io::Write for SharedBuffer
         {
-            write!(f, "{}\n", entry)?;
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+            {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()>
+            {
+                Ok(())
+            }
         }
 
-        write!(f, "\n")
+        let terminal = SharedBuffer::default();
+        let file = SharedBuffer::default();
+
+        drop(ColorGuard::for_writer(terminal.clone(), true));
+        drop(ColorGuard::for_writer(file.clone(), false));
+
+        assert_eq!(*terminal.0.lock().unwrap(), b"\x1B[0m");
+        assert!(file.0.lock().unwrap().is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn box_falls_back_to_ascii_and_wraps_to_the_terminal()
+    {
+        assert_eq!(boxed_with("hi\nthere", None, None, false), "+-------+\n| hi    |\n| there |\n+-------+\n");
+        assert_eq!(boxed_with("one two three", None, Some(12), true), "┌─────────┐\n│ one two │\n│ three   │\n└─────────┘\n");
+    }
+
+    #[test]
+    fn blank_values_are_rejected_by_their_given_name()
+    {
+        let error = |args: Arguments, arg: &str| args.get_passed_non_empty(arg).unwrap_err().error;
+
+        assert_eq!(error(args(&["-n", " "]), "-n"), "argument -n must not be empty");
+        assert_eq!(error(args(&["--name="]), "--name"), "argument --name must not be empty");
+        assert_eq!(error(args(&[]), "--name"), "No '--name' option passed");
+        assert_eq!(args(&["--name", "x"]).get_passed_non_empty("--name").unwrap(), "x");
+    }
+}